@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+
+use savefile::SavefileError;
+
+use crate::{
+    building::BuildingType,
+    history::Action,
+    resources::ResourceBag,
+    settings::data_dir,
+    tile::Pos,
+    Data, EditTool,
+};
+
+/// version of the blueprint library file
+const LIBRARY_VERSION: u32 = 0;
+
+/// the character used for empty ground in the text form of a blueprint
+const GROUND_CHAR: char = '.';
+
+/// a layout of buildings that can be copied from one place and planned somewhere else
+#[derive(Savefile, Clone, PartialEq, Eq, Hash, Default)]
+pub struct Blueprint {
+    pub name: String,
+    /// the buildings in the blueprint, as rows from top to bottom
+    pub rows: Vec<Vec<BuildingType>>,
+}
+
+impl Blueprint {
+    /// copy the buildings between two world cells. land that is not owned is copied as ground, and buildings
+    /// that cover more than one cell are only copied on their anchor
+    pub fn from_world(data: &Data, a: Pos, b: Pos) -> Blueprint {
+        let rows = (a.y.min(b.y)..=a.y.max(b.y))
+            .map(|y| {
+                (a.x.min(b.x)..=a.x.max(b.x))
+                    .map(|x| {
+                        let (tile, cell) = Pos::new(x, y).split_world_cell();
+                        data.tiles
+                            .get(&tile)
+                            .filter(|t| !t.parts.contains_key(&cell))
+                            .map(|t| t.land[cell.x as usize][cell.y as usize].building_type)
+                            .unwrap_or(BuildingType::Ground)
+                    })
+                    .collect()
+            })
+            .collect();
+        Blueprint {
+            name: "Blueprint".to_owned(),
+            rows,
+        }
+    }
+
+    /// read a blueprint from text, with one line per row and one symbol per building
+    pub fn from_text(name: &str, text: &str) -> Result<Blueprint, String> {
+        let mut rows = vec![];
+        for line in text.lines().map(|x| x.trim()).filter(|x| !x.is_empty()) {
+            let mut row = vec![];
+            for c in line.chars() {
+                row.push(match c {
+                    GROUND_CHAR => BuildingType::Ground,
+                    c => BuildingType::from_symbol(&c.to_string())
+                        .ok_or(format!("\"{}\" is not a building", c))?,
+                });
+            }
+            rows.push(row);
+        }
+        if rows.is_empty() {
+            return Err("the blueprint is empty".to_owned());
+        }
+        // pad short rows with ground so that the blueprint is a rectangle
+        let width = rows.iter().map(|x| x.len()).max().unwrap_or(0);
+        for row in &mut rows {
+            row.resize(width, BuildingType::Ground);
+        }
+        Ok(Blueprint {
+            name: name.to_owned(),
+            rows,
+        })
+    }
+
+    /// write the blueprint as text that can be shared and read back in with `from_text`
+    pub fn to_text(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|b| match b {
+                        BuildingType::Ground => GROUND_CHAR.to_string(),
+                        b => b.symbol(),
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows.first().map(|x| x.len()).unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// the blueprint turned a quarter turn clockwise
+    pub fn rotated(&self) -> Blueprint {
+        let height = self.height();
+        self.moved(self.width(), height, |r, c| (c, height - 1 - r))
+    }
+
+    /// the blueprint flipped left to right
+    pub fn mirrored(&self) -> Blueprint {
+        let width = self.width();
+        self.moved(self.height(), width, |r, c| (r, width - 1 - c))
+    }
+
+    /// move every building to where `to` takes its row and column, in a blueprint of a new size. a building
+    /// that covers more than one cell has the cells it covers moved, and its anchor goes on the top left of
+    /// them. buildings cannot be turned, so one that is longer one way keeps its shape
+    fn moved(&self, height: usize, width: usize, to: impl Fn(usize, usize) -> (usize, usize)) -> Blueprint {
+        let mut rows = vec![vec![BuildingType::Ground; width]; height];
+        for (r, row) in self.rows.iter().enumerate() {
+            for (c, b) in row.iter().enumerate() {
+                if *b == BuildingType::Ground {
+                    continue;
+                }
+                // the far corner of the building, kept inside the blueprint in case it was cut off when copied
+                let (footprint_rows, footprint_cols) = b.footprint();
+                let far = (
+                    (r + footprint_rows as usize - 1).min(self.height() - 1),
+                    (c + footprint_cols as usize - 1).min(self.width() - 1),
+                );
+                let (a, z) = (to(r, c), to(far.0, far.1));
+                rows[a.0.min(z.0)][a.1.min(z.1)] = *b;
+            }
+        }
+        Blueprint {
+            name: self.name.clone(),
+            rows,
+        }
+    }
+
+    /// the world cells that the blueprint covers when its top left corner is at `origin`, skipping empty ground
+    pub fn cells(&self, origin: Pos) -> impl Iterator<Item = (Pos, BuildingType)> + '_ {
+        self.rows.iter().enumerate().flat_map(move |(r, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|x| x.1 != &BuildingType::Ground)
+                .map(move |(c, b)| (Pos::new(origin.x + c as i32, origin.y + r as i32), *b))
+        })
+    }
+
+    /// the total cost of every building in the blueprint
+    pub fn cost(&self) -> ResourceBag {
+        let mut cost = ResourceBag::default();
+        for b in self.rows.iter().flatten() {
+            cost += ResourceBag::of(&b.cost());
+        }
+        cost
+    }
+}
+
+impl Data {
+    /// plan every building in a blueprint with its top left corner at a world cell
+    pub fn paste_blueprint(&mut self, blueprint: &Blueprint, origin: Pos) {
+        let mut before = HashMap::new();
+        let mut skipped = 0;
+        for (cell, b) in blueprint.cells(origin) {
+            let (tile_pos, cell) = cell.split_world_cell();
+            let tile = match self.tiles.get_mut(&tile_pos) {
+                Some(t) => t,
+                None => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            before.entry(tile_pos).or_insert_with(|| tile.snapshot());
+            if tile.land[cell.x as usize][cell.y as usize].building_type != b {
+                tile.planned.insert(cell, b);
+            }
+        }
+        for pos in before.keys() {
+            self.sync_tile_queue(*pos);
+        }
+        let actions = before
+            .into_iter()
+            .map(|(pos, state)| Action::Edit {
+                tile: pos,
+                before: Box::new(state),
+                after: Box::new(self.tiles[&pos].snapshot()),
+                resources: ResourceBag::default(),
+            })
+            .collect::<Vec<Action>>();
+        if !actions.is_empty() {
+            self.history.push(Action::Group(actions));
+        }
+        if skipped > 0 {
+            self.warn(format!("{} buildings were outside of your land", skipped));
+        }
+    }
+}
+
+/// the blueprints that the player has saved. these are shared between all cities
+#[derive(Savefile, Default)]
+pub struct BlueprintLibrary {
+    pub blueprints: Vec<Blueprint>,
+
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub open: bool,
+
+    /// text pasted in by the player to be read as a blueprint
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    import_text: String,
+}
+
+fn library_path() -> std::path::PathBuf {
+    data_dir().join("blueprints.bin")
+}
+
+impl BlueprintLibrary {
+    /// load the library, or an empty one if there isn't one
+    pub fn load() -> BlueprintLibrary {
+        savefile::load_file(library_path(), LIBRARY_VERSION).unwrap_or_default()
+    }
+
+    pub fn save(&self) -> Result<(), SavefileError> {
+        std::fs::create_dir_all(data_dir())?;
+        savefile::save_file(library_path(), LIBRARY_VERSION, self)
+    }
+
+    /// render the blueprint window
+    pub fn render(&mut self, egui_ctx: &egui::Context, data: &mut Data) {
+        let mut changed = false;
+        let mut open = self.open;
+
+        egui::Window::new(format!("{} Blueprints", egui_phosphor::STAMP))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                match &mut data.input_settings.edit_tool {
+                    EditTool::Blueprint(b) => {
+                        ui.horizontal(|ui| {
+                            ui.label("Name");
+                            ui.text_edit_singleline(&mut b.name);
+                        });
+                        ui.group(|ui| {
+                            ui.monospace(b.to_text());
+                        });
+                        ui.small(format!(
+                            "costs {}",
+                            b.cost()
+                                .iter()
+                                .map(|x| format!("{}{} ", x.0.symbol(), x.1))
+                                .collect::<String>()
+                        ));
+                        ui.horizontal(|ui| {
+                            if ui.small_button(format!("{} rotate (R)", egui_phosphor::ARROW_CLOCKWISE)).clicked() {
+                                *b = b.rotated();
+                            }
+                            if ui.small_button(format!("{} mirror (F)", egui_phosphor::ARROWS_LEFT_RIGHT)).clicked() {
+                                *b = b.mirrored();
+                            }
+                        });
+                        ui.horizontal(|ui| {
+                            if ui.small_button(format!("{} save to library", egui_phosphor::FLOPPY_DISK)).clicked() {
+                                self.blueprints.retain(|x| x.name != b.name);
+                                self.blueprints.push(b.clone());
+                                changed = true;
+                            }
+                            if ui.small_button(format!("{} copy text", egui_phosphor::CLIPBOARD_TEXT)).clicked() {
+                                ui.output_mut(|o| o.copied_text = b.to_text());
+                            }
+                        });
+                        ui.small("click on your land to plan the blueprint with its top left corner there");
+                    }
+                    tool => {
+                        ui.small("use the copy tool and click on two corners to copy the buildings between them, or hold C over a tile to copy all of it");
+                        if ui.small_button(format!("{} copy tool", egui_phosphor::SELECTION)).clicked() {
+                            *tool = EditTool::Copy;
+                        }
+                    }
+                }
+
+                ui.separator();
+                ui.label("Import");
+                ui.add(
+                    egui::TextEdit::multiline(&mut self.import_text)
+                        .code_editor()
+                        .desired_rows(3)
+                        .hint_text("paste a blueprint here"),
+                );
+                if ui.small_button("Import").clicked() {
+                    match Blueprint::from_text("Imported", &self.import_text) {
+                        Ok(b) => data.input_settings.edit_tool = EditTool::Blueprint(b),
+                        Err(e) => data.error(e),
+                    }
+                }
+
+                ui.separator();
+                ui.heading("Library");
+                if self.blueprints.is_empty() {
+                    ui.small("no blueprints have been saved yet");
+                }
+                let mut remove = None;
+                for (i, b) in self.blueprints.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .small_button(format!("{} {}", egui_phosphor::STAMP, b.name))
+                            .on_hover_text(egui::RichText::new(b.to_text()).monospace())
+                            .clicked()
+                        {
+                            data.input_settings.edit_tool = EditTool::Blueprint(b.clone());
+                        }
+                        ui.small(format!("{}x{}", b.width(), b.height()));
+                        if ui.small_button(egui_phosphor::TRASH).clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                }
+                if let Some(i) = remove {
+                    self.blueprints.remove(i);
+                    changed = true;
+                }
+            });
+        self.open = open;
+
+        if changed {
+            if let Err(e) = self.save() {
+                data.error(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::building::BuildingType::{Grain, Ground, House, Tree};
+
+    /// two rows of three:
+    /// ```text
+    /// H T .
+    /// . . G
+    /// ```
+    fn sample() -> Blueprint {
+        Blueprint {
+            name: "sample".to_owned(),
+            rows: vec![vec![House, Tree, Ground], vec![Ground, Ground, Grain]],
+        }
+    }
+
+    #[test]
+    fn rotated_turns_clockwise() {
+        let rotated = sample().rotated();
+        assert_eq!((rotated.width(), rotated.height()), (2, 3));
+        assert!(rotated.rows == vec![vec![Ground, House], vec![Ground, Tree], vec![Grain, Ground]]);
+        // four quarter turns get back to the start
+        assert!(sample().rotated().rotated().rotated().rotated() == sample());
+    }
+
+    #[test]
+    fn mirrored_flips_left_to_right() {
+        let mirrored = sample().mirrored();
+        assert!(mirrored.rows == vec![vec![Ground, Tree, House], vec![Grain, Ground, Ground]]);
+        assert!(mirrored.mirrored() == sample());
+    }
+
+    #[test]
+    fn multi_cell_buildings_keep_their_anchor_on_the_top_left() {
+        use crate::building::BuildingType::{NuclearPowerPlant, Runway};
+        // a 2x2 building in the top left of a 3x3 blueprint, with the cells it covers left as ground
+        let blueprint = Blueprint {
+            name: "plant".to_owned(),
+            rows: vec![vec![NuclearPowerPlant, Ground, House], vec![Ground, Ground, Ground], vec![Ground, Ground, Ground]],
+        };
+        assert!(
+            blueprint.rotated().rows
+                == vec![vec![Ground, NuclearPowerPlant, Ground], vec![Ground, Ground, Ground], vec![Ground, Ground, House]]
+        );
+        assert!(
+            blueprint.mirrored().rows
+                == vec![vec![House, NuclearPowerPlant, Ground], vec![Ground, Ground, Ground], vec![Ground, Ground, Ground]]
+        );
+        assert!(blueprint.rotated().rotated().rotated().rotated() == blueprint);
+
+        // a runway covers a row of four cells, so it is anchored on the top of the column it turns into
+        let runway = Blueprint {
+            name: "runway".to_owned(),
+            rows: vec![vec![Ground, Ground, Ground, Ground], vec![Runway, Ground, Ground, Ground]],
+        };
+        let rotated = runway.rotated();
+        assert!(rotated.rows.iter().flatten().filter(|x| **x == Runway).count() == 1);
+        assert!(rotated.rows[0][0] == Runway);
+        assert!(runway.mirrored().rows[1][0] == Runway);
+    }
+
+    #[test]
+    fn text_round_trip() {
+        let text = sample().to_text();
+        assert_eq!(text.lines().count(), 2);
+        assert!(text.lines().nth(1).unwrap().starts_with(".."));
+        assert!(Blueprint::from_text("sample", &text).ok() == Some(sample()));
+    }
+
+    #[test]
+    fn from_text_pads_short_rows() {
+        let text = format!("{}\n\n.{}{}\n", House.symbol(), Tree.symbol(), Grain.symbol());
+        let blueprint = Blueprint::from_text("padded", &text).unwrap();
+        assert!(blueprint.rows == vec![vec![House, Ground, Ground], vec![Ground, Tree, Grain]]);
+    }
+
+    #[test]
+    fn from_text_rejects_bad_input() {
+        assert!(Blueprint::from_text("empty", " \n\n").is_err());
+        assert_eq!(Blueprint::from_text("bad", "x").err(), Some("\"x\" is not a building".to_owned()));
+    }
+}
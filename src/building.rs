@@ -1,5 +1,5 @@
 use std::vec;
-use strum::EnumIter;
+use strum::{EnumCount, EnumIter};
 use crate::BuildingType::*;
 
 use strum::IntoEnumIterator;
@@ -43,6 +43,7 @@ impl Stage {
                     Factory,
                     Battery,
                     SteelProduction,
+                    Garage,
                 ],
                 4 => vec![
                     BasicResearchFacility,
@@ -58,25 +59,26 @@ impl Stage {
                     PoliceStation,
                     Hospital,
                     FoodTruck,
+                    Turret,
                     // Cpu,
                 ],
-                // 6 => vec![
+                6 => vec![
+                    AirTrafficControl,
+                    Runway,
+                    NuclearPowerPlant,
+                    Rocket,
+                ],
+                // 7 => vec![
                 //     Lightning,
                 //     Siren,
-                //     AirTrafficControl,
-                //     Runway,
                 //     StairsIntoTheVoid,
-                //     Garage,
                 //     LightHouse,
                 //     Lightbulb,
                 //     Mosque,
-                //     NuclearPowerPlant,
-                //     Rocket,
                 //     RobotFactory,
                 //     Cookie,
                 //     Database,
                 //     PalmTree,
-                //     Turret,
                 // ],
                 _ => vec![],
             },
@@ -87,7 +89,7 @@ impl Stage {
                 3 => "The industrial revolution has arrived!. Factories can be used to operate a wide range of things, including steel mills and power plants.".to_owned(),
                 4 => "Build a basic research facility, a concrete mixer, a gauge, and an asphalt plant.".to_owned(),
                 5 => "Expand into a city, with asphalt instep of icky dirt (hint everything in a city needs road access)".to_owned(),
-                6 => "TODO".to_owned(),
+                6 => "Build an airport and a launch site. These buildings are big, so they take up more than one cell".to_owned(),
                 a => format!("stage {a} has no description"),
             },
             title: match num {
@@ -96,7 +98,7 @@ impl Stage {
                 3 => "Industrial Revolution".to_owned(),
                 4 => "Research".to_owned(),
                 5 => "City".to_owned(),
-                6 => "TODO".to_owned(),
+                6 => "Lift Off".to_owned(),
                 a => format!("stage {a} has no title"),
             },
             enabled: num == 1,
@@ -106,6 +108,7 @@ impl Stage {
                 3 => vec![(Resource::Wood, 100)],
                 4 => vec![(Resource::Storage, 900)],
                 5 => vec![(Resource::Concrete, 1200)],
+                6 => vec![(Resource::Concrete, 3000), (Resource::BasicScience, 1000)],
                 _ => vec![(Resource::PlaceholderResource, 9999)],
             },
         }
@@ -113,6 +116,9 @@ impl Stage {
 }
 
 
+/// the buildings in the city. they can all be next to each other, and they all need to be next to a road
+pub const CITY_TILES: [BuildingType; 7] = [Bank, FireStation, PoliceStation, Hospital, Apartment, FoodTruck, Cpu];
+
 #[derive(PartialEq, Eq, Hash, Copy, Clone, EnumIter, Savefile)]
 pub enum BuildingType {
     Ground,
@@ -141,6 +147,7 @@ pub enum BuildingType {
     Runway,
     Cpu,
     StairsIntoTheVoid,
+    /// the large warehouse. it keeps its old name so that older saves still load
     Garage,
     LightHouse,
     Lightbulb,
@@ -151,12 +158,22 @@ pub enum BuildingType {
     Cookie,
     Database,
     PalmTree,
+    /// the town centre, where the road network starts. it keeps its old name so that older saves still load
     Turret,
     
 
 }
 
 impl BuildingType {
+    /// find the building that is drawn with a symbol. the reverse of `symbol`
+    /// # Example
+    /// ```
+    /// let building = BuildingType::from_symbol(&BuildingType::House.symbol());
+    /// assert_eq!(building, Some(BuildingType::House));
+    /// ```
+    pub fn from_symbol(symbol: &str) -> Option<BuildingType> {
+        BuildingType::iter().find(|x| x.symbol() == symbol)
+    }
     pub fn symbol(&self) -> String {
         match self {
             Ground => "  ",
@@ -234,7 +251,7 @@ impl BuildingType {
             Runway => "Runway",
             Cpu => "Computational Research Facility",
             StairsIntoTheVoid => "Stairs Into The Void",
-            Garage => "Garage",
+            Garage => "Large Warehouse",
             LightHouse => "Light House",
             Lightbulb => "Lightbulb",
             Mosque => "Mosque",
@@ -244,7 +261,7 @@ impl BuildingType {
             Cookie => "Cookie",
             Database => "Database",
             PalmTree => "Palm Tree",
-            Turret => "Turret",
+            Turret => "Town Centre",
 
         }.to_owned()
     }
@@ -263,6 +280,7 @@ impl BuildingType {
             Tree => vec![(Resource::Wood, 1)],
             Shop => vec![(Resource::Tax, 2)],
             Warehouse => vec![(Resource::Storage, 100)],
+            Garage => vec![(Resource::Storage, 300)],
             Battery => vec![],
             SteelProduction => vec![(Resource::Steel, 1)],
             Bank => vec![(Resource::CashStorage, 1000)],
@@ -279,23 +297,22 @@ impl BuildingType {
             |Factory
             => vec![],// the following buildings have no output
             FoodTruck => vec![(Resource::Tax, 25)],
+            AirTrafficControl => vec![(Resource::Tax, 10)],
+            Runway => vec![(Resource::Tax, 100)],
+            NuclearPowerPlant => vec![(Resource::BasicScience, 5)],
+            Rocket => vec![(Resource::BasicScience, 20)],
             Lightning
-            |AirTrafficControl
-            |Runway
             |StairsIntoTheVoid
-            |Garage
             |LightHouse
             |Lightbulb
             |Mosque
-            |NuclearPowerPlant
-            |Rocket
             |RobotFactory
             |Cookie
             |Database
             |PalmTree
-            |Turret
             |Siren
             => vec![(Resource::PlaceholderResource, 0)],
+            Turret => vec![(Resource::Tax, 5)],
             
         }
     }
@@ -315,6 +332,7 @@ impl BuildingType {
             House => vec![(Resource::Wood, 10), (Resource::Food, 10)],
             Grain|Tree => vec![(Resource::Seed, 5)],
             Warehouse => vec![(Resource::Wood, 100)],
+            Garage => vec![(Resource::Wood, 250), (Resource::Steel, 30)],
             Battery => vec![(Resource::Steel, 20), (Resource::Food, 200)],
             Factory => vec![(Resource::Wood, 100), (Resource::Food, 100), (Resource::Seed, 100)], // placeholder
             SteelProduction => vec![(Resource::Wood, 150)],
@@ -326,32 +344,116 @@ impl BuildingType {
             Hospital => vec![(Resource::Concrete, 1000), (Resource::Food, 1500), (Resource::BasicScience, 50)],
             PoliceStation => vec![(Resource::Concrete, 500), (Resource::Food, 500)],
             FoodTruck => vec![(Resource::Food, 5000), (Resource::Wood, 1000)],
+            AirTrafficControl => vec![(Resource::Concrete, 800), (Resource::Steel, 100)],
+            Runway => vec![(Resource::Concrete, 1500)],
+            NuclearPowerPlant => vec![(Resource::Concrete, 2000), (Resource::Steel, 500), (Resource::BasicScience, 500)],
+            Rocket => vec![(Resource::Steel, 1500), (Resource::BasicScience, 1000), (Resource::Food, 2000)],
             Siren
             |LightHouse
             |Lightning
-            |AirTrafficControl
-            |Runway
             |Cpu
             |StairsIntoTheVoid
-            |Garage
             |Lightbulb
             |Mosque
-            |NuclearPowerPlant
-            |Rocket
             |RobotFactory
             |Cookie
             |Database
             |PalmTree
-            |Turret
             => vec![(Resource::PlaceholderResource, 0)],
+            Turret => vec![(Resource::Concrete, 200), (Resource::Wood, 200)],
         };
         return cost
     }
+
+    /// how many cells the building covers, as rows and columns. the top left cell is its anchor
+    /// # Example
+    /// ```
+    /// assert_eq!(BuildingType::Runway.footprint(), (1, 4));
+    /// ```
+    pub fn footprint(&self) -> (i32, i32) {
+        match self {
+            Runway => (1, 4),
+            AirTrafficControl => (2, 1),
+            NuclearPowerPlant | Rocket => (2, 2),
+            _ => (1, 1),
+        }
+    }
+
+    /// check if the building covers more than one cell
+    pub fn is_multi_cell(&self) -> bool {
+        self.footprint() != (1, 1)
+    }
+
+    /// check if the building needs to be next to a road that leads back to a road hub
+    pub fn needs_road(&self) -> bool {
+        CITY_TILES.contains(self)
+    }
+
+    /// check if roads next to the building count as connected to the road network
+    pub fn is_road_hub(&self) -> bool {
+        matches!(self, Turret | Runway)
+    }
+
+    /// the building that this one can be upgraded to in place, if there is one
+    /// # Example
+    /// ```
+    /// assert_eq!(BuildingType::House.upgrade(), Some(BuildingType::Apartment));
+    /// ```
+    pub fn upgrade(&self) -> Option<BuildingType> {
+        match self {
+            House => Some(Apartment),
+            Grain => Some(Carrot),
+            Warehouse => Some(Garage),
+            _ => None,
+        }
+    }
+
+    /// the colour used for the building in summaries like the minimap, grouped by what the building is for
+    pub fn color(&self) -> egui::Color32 {
+        let (r, g, b) = match self {
+            Ground => (225, 225, 215),
+            House | Apartment => (220, 170, 90),
+            Grain | Carrot | Cookie | FoodTruck => (230, 210, 90),
+            Tree | PalmTree => (80, 160, 80),
+            Shop | Bank | Turret => (90, 170, 210),
+            Warehouse | Garage => (170, 130, 90),
+            Factory | SteelProduction | ConcreteMixer | RobotFactory => (140, 140, 150),
+            BasicResearchFacility | Cpu | Database | NuclearPowerPlant | Rocket => (160, 110, 200),
+            Asphalt => (90, 90, 90),
+            AirTrafficControl | Runway => (70, 90, 140),
+            FireStation | PoliceStation | Hospital | Siren => (210, 90, 90),
+            _ => (190, 190, 180),
+        };
+        egui::Color32::from_rgb(r, g, b)
+    }
+
+    /// what has to be paid to swap this building for another one in place. only the difference
+    /// in cost is paid, and resources that the new building needs less of are refunded as negative amounts
+    /// # Example
+    /// ```
+    /// let cost = BuildingType::Grain.replace_cost(&BuildingType::Carrot);
+    /// assert_eq!(cost, vec![(Resource::Seed, 45)]);
+    /// ```
+    pub fn replace_cost(&self, to: &BuildingType) -> Vec<(Resource, i32)> {
+        let old = self.cost();
+        let new = to.cost();
+        let mut cost = new
+            .iter()
+            .map(|x| (x.0, x.1 - old.iter().filter(|o| o.0 == x.0).map(|o| o.1).sum::<i32>()))
+            .collect::<Vec<(Resource, i32)>>();
+        for o in old {
+            if !new.iter().any(|x| x.0 == o.0) {
+                cost.push((o.0, -o.1));
+            }
+        }
+        cost.retain(|x| x.1 != 0);
+        cost
+    }
 }
 
 
 // list of all resources that a building can output
-#[derive(PartialEq, Eq, Hash, Copy, Clone, EnumIter, Savefile)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, EnumIter, EnumCount, Savefile)]
 pub enum Resource {
     Food,
     Tax,
@@ -411,6 +513,15 @@ impl Resource {
             Resource::PlaceholderResource => "Placeholder Resource",
         }.to_owned()
     }
+
+    /// check if the resource is a physical good. with logistics turned on goods are kept in the
+    /// stockpile of the tile that made them and have to be moved to where they are spent
+    pub fn is_goods(&self) -> bool {
+        matches!(
+            self,
+            Resource::Food | Resource::Wood | Resource::Seed | Resource::Steel | Resource::Concrete
+        )
+    }
 }
 
 
@@ -455,7 +566,7 @@ impl Building {
 
 
         // these buildings can all be next to each other. I use this to make this process easer and less repetitive. 
-        let city_tiles = [Bank, FireStation, PoliceStation, Hospital, Apartment, FoodTruck, Cpu];
+        let city_tiles = CITY_TILES;
 
         let mut optional_adj = vec![Ground];
 
@@ -464,6 +575,8 @@ impl Building {
         // if two buildings should be valid nex to each other than they can be put as a tuple here. 
         for i in [
             (Warehouse, Shop),
+            (Garage, Shop),
+            (Garage, Warehouse),
             (Battery, Factory),
             (SteelProduction, Factory),
             (House, BasicResearchFacility),
@@ -471,6 +584,11 @@ impl Building {
             (Factory, ConcreteMixer),
             (ConcreteMixer, Gauge),
             (Grain, Carrot),
+            (Runway, AirTrafficControl),
+            (Runway, Asphalt),
+            (AirTrafficControl, Asphalt),
+            (Rocket, NuclearPowerPlant),
+            (Turret, Asphalt),
             ] {
             if &i.0 == building_type {optional_adj.push(i.1)}
             if &i.1 == building_type {optional_adj.push(i.0)}
@@ -499,6 +617,8 @@ impl Building {
             Grain|Tree|Carrot => vec![House],
             Factory => vec![],
             Apartment => vec![FireStation, Hospital, PoliceStation],
+            Runway => vec![AirTrafficControl],
+            Rocket => vec![NuclearPowerPlant],
             Ground|House|Warehouse|Battery|SteelProduction => Vec::new(),
             BasicResearchFacility
             |Gauge
@@ -513,14 +633,12 @@ impl Building {
             |LightHouse
             |Lightning
             |AirTrafficControl
-            |Runway
             |Cpu
             |StairsIntoTheVoid
             |Garage
             |Lightbulb
             |Mosque
             |NuclearPowerPlant
-            |Rocket
             |RobotFactory
             |Cookie
             |Database
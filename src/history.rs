@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+
+use crate::{
+    building::Resource,
+    loan,
+    resources::ResourceBag,
+    tile::{Pos, Tile, TileState},
+    Data,
+};
+
+/// the most actions that can be undone at once
+const MAX_ACTIONS: usize = 50;
+
+/// how many economy ticks an action can be undone for. once the economy has moved on
+/// the resources that an action spent or refunded have usually been used, so it is dropped
+const MAX_AGE_TICKS: u64 = 20;
+
+/// something the player did that can be undone
+pub enum Action {
+    /// the buildings or plans on a tile were changed. `resources` is how much of each resource
+    /// was gained by the change (negative if it was spent)
+    Edit {
+        tile: Pos,
+        before: Box<TileState>,
+        after: Box<TileState>,
+        resources: ResourceBag,
+    },
+    /// a piece of land was bought. `borrowed` is the part of the cost that was taken as a loan
+    BuyLand { pos: Pos, cost: i32, borrowed: i32 },
+    /// a piece of empty land was sold back
+    SellLand { pos: Pos, refund: i32 },
+    /// several actions that were made at once, such as a blueprint that covers more than one tile
+    Group(Vec<Action>),
+}
+
+impl Action {
+    /// check if the action changes the buildings or plans on a tile
+    fn edits(&self, pos: Pos) -> bool {
+        match self {
+            Action::Edit { tile, .. } => *tile == pos,
+            Action::BuyLand { .. } | Action::SellLand { .. } => false,
+            Action::Group(actions) => actions.iter().any(|a| a.edits(pos)),
+        }
+    }
+}
+
+/// the list of actions that can be undone and redone
+#[derive(Default)]
+pub struct History {
+    /// actions that can be undone, along with the tick they were made on
+    undo: VecDeque<(u64, Action)>,
+    redo: Vec<Action>,
+    tick: u64,
+}
+
+impl History {
+    /// record an action that the player has just made. this clears the redo list
+    pub fn push(&mut self, action: Action) {
+        self.redo.clear();
+        self.undo.push_back((self.tick, action));
+        if self.undo.len() > MAX_ACTIONS {
+            self.undo.pop_front();
+        }
+    }
+
+    /// called every economy tick to drop actions that are too old to undo
+    pub fn on_tick(&mut self) {
+        self.tick += 1;
+        while let Some((t, _)) = self.undo.front() {
+            if t + MAX_AGE_TICKS >= self.tick {
+                break;
+            }
+            self.undo.pop_front();
+        }
+    }
+
+    /// forget everything. used when the economy passes a point that cannot be reversed, like unlocking a stage
+    pub fn clear(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+    }
+
+    /// forget every action that changed the buildings on a tile. used when the tile is changed by
+    /// something that is not in the history, since putting back an old state would lose the change
+    pub fn forget_tile(&mut self, tile: Pos) {
+        self.undo.retain(|(_, a)| !a.edits(tile));
+        self.redo.retain(|a| !a.edits(tile));
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+}
+
+/// add `sign * delta` to the resources, as long as it does not leave any of them negative.
+/// returns the first resource that there is not enough of
+fn apply_delta(resources: &mut ResourceBag, delta: &ResourceBag, sign: i64) -> Result<(), Resource> {
+    // gaining resources is the same as paying a negative cost
+    resources.pay(&(*delta * -sign))
+}
+
+impl Data {
+    /// undo the last action. returns true if something was undone
+    pub fn undo(&mut self) -> bool {
+        let (tick, action) = match self.history.undo.pop_back() {
+            Some(a) => a,
+            None => return false,
+        };
+        match self.apply_action(&action, true) {
+            Ok(_) => {
+                self.history.redo.push(action);
+                true
+            }
+            Err(msg) => {
+                self.warn(format!("cannot undo: {}", msg));
+                self.history.undo.push_back((tick, action));
+                false
+            }
+        }
+    }
+
+    /// redo the last action that was undone. returns true if something was redone
+    pub fn redo(&mut self) -> bool {
+        let action = match self.history.redo.pop() {
+            Some(a) => a,
+            None => return false,
+        };
+        match self.apply_action(&action, false) {
+            Ok(_) => {
+                let tick = self.history.tick;
+                self.history.undo.push_back((tick, action));
+                true
+            }
+            Err(msg) => {
+                self.warn(format!("cannot redo: {}", msg));
+                self.history.redo.push(action);
+                false
+            }
+        }
+    }
+
+    /// apply an action, or reverse it if `reverse` is true
+    fn apply_action(&mut self, action: &Action, reverse: bool) -> Result<(), String> {
+        let sign = if reverse { -1 } else { 1 };
+        match action {
+            Action::Edit {
+                tile,
+                before,
+                after,
+                resources,
+            } => {
+                if !self.tiles.contains_key(tile) {
+                    return Err("the land is no longer owned".to_owned());
+                }
+                let state = if reverse { before } else { after };
+                // the resources go back to where they were spent from, which is the tile's stockpile for goods in logistics mode
+                self.with_funds(*tile, |t, funds| {
+                    apply_delta(funds, resources, sign).map_err(|r| format!("not enough {}", r.name()))?;
+                    t.restore(state, funds);
+                    Ok::<(), String>(())
+                })
+                .unwrap_or(Ok(()))?;
+                self.add_buildings(tile.get_adjacent());
+            }
+            Action::BuyLand { pos, cost, borrowed } => {
+                let cash = ResourceBag::of(&[(Resource::Tax, cost - borrowed)]);
+                if reverse {
+                    // the land has to be empty to give it back. anything built on it was
+                    // done after it was bought, so it would have been undone first
+                    match self.tiles.get(pos) {
+                        Some(t) if t.snapshot() == Tile::new(*pos).snapshot() && t.stock.is_empty() => {}
+                        _ => return Err("the land is not empty".to_owned()),
+                    }
+                    // the borrowed part goes back to the bank, and only the tax that was paid is refunded
+                    let (storage, cash_storage) = self.storage_caps();
+                    self.forgive_loan(*borrowed);
+                    self.resources.refund(&cash, storage + cash_storage);
+                    self.tiles.remove(pos);
+                } else {
+                    if self.tiles.contains_key(pos) {
+                        return Err("the land is already owned".to_owned());
+                    }
+                    if *borrowed > self.available_credit() {
+                        return Err("the bank will not lend enough".to_owned());
+                    }
+                    self.resources.pay(&cash).map_err(|r| format!("not enough {}", r.name()))?;
+                    if *borrowed > 0 {
+                        self.take_loan(*borrowed, loan::DEFAULT_TERM);
+                    }
+                    self.tiles.insert(*pos, Tile::new(*pos));
+                }
+                self.update_new_pos();
+            }
+            Action::SellLand { pos, refund } => {
+                if reverse {
+                    if self.tiles.contains_key(pos) {
+                        return Err("the land is already owned".to_owned());
+                    }
+                } else {
+                    match self.tiles.get(pos) {
+                        Some(t) if t.snapshot() == Tile::new(*pos).snapshot() && t.stock.is_empty() => {}
+                        _ => return Err("the land is not empty".to_owned()),
+                    }
+                }
+                let delta = ResourceBag::of(&[(Resource::Tax, *refund)]);
+                apply_delta(&mut self.resources, &delta, sign)
+                    .map_err(|r| format!("not enough {}", r.name()))?;
+                if reverse {
+                    self.tiles.insert(*pos, Tile::new(*pos));
+                } else {
+                    self.tiles.remove(pos);
+                }
+                self.update_new_pos();
+                for p in pos.get_adjacent() {
+                    self.add_buildings(p.get_adjacent());
+                }
+            }
+            Action::Group(actions) => {
+                // undo the actions in the opposite order to how they were made. if one of them
+                // fails then the ones that were already applied are put back
+                let order: Vec<&Action> = match reverse {
+                    true => actions.iter().rev().collect(),
+                    false => actions.iter().collect(),
+                };
+                for (i, a) in order.iter().enumerate() {
+                    if let Err(e) = self.apply_action(a, reverse) {
+                        for done in order[..i].iter().rev() {
+                            let _ = self.apply_action(done, !reverse);
+                        }
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
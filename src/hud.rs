@@ -0,0 +1,212 @@
+use egui::{Color32, RichText};
+use strum::IntoEnumIterator;
+
+use crate::{building::Resource, saves::format_duration, tutorial::Target, Data, TICK_SECS};
+
+/// colour of a resource that has reached its storage cap
+const CAPPED_COLOR: Color32 = Color32::from_rgb(200, 130, 30);
+/// colour of a resource that is going down
+const FALLING_COLOR: Color32 = Color32::from_rgb(200, 60, 60);
+
+/// the number of ticks the rates in the HUD are averaged over, which is a minute of play
+const RATE_TICKS: usize = 20;
+
+/// how the resources in the top left corner are shown. each city keeps its own
+#[derive(Savefile, Clone, Default)]
+pub struct Hud {
+    /// show the rate, cap and time to full of every resource instead of just the amount
+    pub detail: bool,
+    /// resources shown first, in the order they were pinned. pinned resources are shown even when there is none
+    pub pinned: Vec<Resource>,
+    /// resources that are not shown at all
+    pub hidden: Vec<Resource>,
+}
+
+impl Hud {
+    /// pin a resource to the top, or unpin it if it already is
+    fn toggle_pin(&mut self, resource: Resource) {
+        match self.pinned.iter().position(|x| *x == resource) {
+            Some(i) => {
+                self.pinned.remove(i);
+            }
+            None => self.pinned.push(resource),
+        }
+    }
+
+    fn hide(&mut self, resource: Resource) {
+        self.pinned.retain(|x| *x != resource);
+        if !self.hidden.contains(&resource) {
+            self.hidden.push(resource);
+        }
+    }
+}
+
+/// one line of the HUD
+struct Row {
+    resource: Resource,
+    amount: i64,
+    cap: i64,
+    /// the change per tick
+    rate: f32,
+}
+
+impl Row {
+    fn capped(&self) -> bool {
+        self.amount >= self.cap
+    }
+
+    fn color(&self) -> Option<Color32> {
+        if self.rate < 0.0 {
+            Some(FALLING_COLOR)
+        } else if self.capped() {
+            Some(CAPPED_COLOR)
+        } else {
+            None
+        }
+    }
+
+    /// how long until the resource is full or runs out at the current rate
+    fn eta(&self) -> String {
+        if self.capped() {
+            "full".to_owned()
+        } else if self.rate > 0.0 {
+            format!("full in {}", format_duration(((self.cap - self.amount) as f32 / self.rate).ceil() as u64 * TICK_SECS))
+        } else if self.rate < 0.0 && self.amount > 0 {
+            format!("empty in {}", format_duration((self.amount as f32 / -self.rate).ceil() as u64 * TICK_SECS))
+        } else {
+            "".to_owned()
+        }
+    }
+
+    fn text(&self, text: String) -> RichText {
+        match self.color() {
+            Some(c) => RichText::new(text).color(c),
+            None => RichText::new(text),
+        }
+    }
+}
+
+impl Data {
+    /// the most of a resource that can be kept. tax can also be kept as cash
+    pub fn cap(&self, resource: Resource) -> i64 {
+        let (storage, cash_storage) = self.storage_caps();
+        match resource {
+            Resource::Tax | Resource::CashStorage => storage + cash_storage,
+            _ => storage,
+        }
+    }
+
+    /// the resources to show in the HUD, pinned ones first and then the rest in their usual order
+    fn hud_rows(&self) -> Vec<Row> {
+        let totals = self.totals();
+        // the rate is the change in the totals over the last minute, so that spending and the market
+        // count too. until there is enough history the production of the last tick is used
+        let snapshots = self.stats.recent(RATE_TICKS);
+        let rate = |r: Resource| match snapshots.len() {
+            0 | 1 => self.per_tick.get(r) as f32,
+            n => (snapshots[n - 1].resources.get(r) - snapshots[0].resources.get(r)) as f32 / (n - 1) as f32,
+        };
+        let rest = Resource::iter().filter(|r| !self.hud.pinned.contains(r) && totals.get(*r) != 0);
+        self.hud
+            .pinned
+            .iter()
+            .copied()
+            .chain(rest)
+            .filter(|r| !self.hud.hidden.contains(r))
+            .map(|r| Row {
+                resource: r,
+                amount: totals.get(r),
+                cap: self.cap(r),
+                rate: rate(r),
+            })
+            .collect()
+    }
+
+    /// render the resources in the top left corner
+    pub fn render_hud(&mut self, egui_ctx: &egui::Context) {
+        let rows = self.hud_rows();
+        let mut hud = self.hud.clone();
+        let mut marks = vec![];
+        egui::Area::new("data")
+            .fixed_pos(egui::Pos2::new(5.0, 5.0))
+            .order(egui::Order::Foreground)
+            .show(egui_ctx, |ui| {
+                egui::Frame::group(ui.style())
+                    .fill(ui.visuals().window_fill())
+                    .show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let icon = match hud.detail {
+                                true => egui_phosphor::CARET_UP,
+                                false => egui_phosphor::CARET_DOWN,
+                            };
+                            if ui.small_button(icon).on_hover_text("show more or less about each resource").clicked() {
+                                hud.detail = !hud.detail;
+                            }
+                            if !hud.hidden.is_empty() {
+                                ui.menu_button(format!("{} {}", egui_phosphor::EYE_SLASH, hud.hidden.len()), |ui| {
+                                    let mut shown = None;
+                                    for r in &hud.hidden {
+                                        if ui.button(format!("show {} {}", r.symbol(), r.name())).clicked() {
+                                            shown = Some(*r);
+                                            ui.close_menu();
+                                        }
+                                    }
+                                    if let Some(r) = shown {
+                                        hud.hidden.retain(|x| *x != r);
+                                    }
+                                });
+                            }
+                        });
+                        egui::Grid::new("grid").show(ui, |ui| {
+                            for row in &rows {
+                                let r = row.resource;
+                                let pin = match hud.pinned.contains(&r) {
+                                    true => egui_phosphor::PUSH_PIN,
+                                    false => "",
+                                };
+                                let name = match hud.detail {
+                                    true => format!("{} {} {}", r.symbol(), r.name(), pin),
+                                    false => format!("{} {} {}", r.symbol(), row.amount, pin),
+                                };
+                                let label = ui.add(egui::Label::new(row.text(name)).sense(egui::Sense::click()));
+                                if hud.detail {
+                                    ui.label(row.text(format!("{} / {}", row.amount, row.cap)));
+                                    ui.label(row.text(format!("{:+.1}/s", row.rate / TICK_SECS as f32)));
+                                    ui.label(row.text(row.eta()));
+                                }
+                                let label = match hud.detail {
+                                    true => label,
+                                    false => label.on_hover_text(format!(
+                                        "{} of {}, {:+.1}/s {}",
+                                        row.amount,
+                                        row.cap,
+                                        row.rate / TICK_SECS as f32,
+                                        row.eta()
+                                    )),
+                                };
+                                marks.push((r, label.rect));
+                                label.context_menu(|ui| {
+                                    let text = match hud.pinned.contains(&r) {
+                                        true => "unpin",
+                                        false => "pin to the top",
+                                    };
+                                    if ui.button(text).clicked() {
+                                        hud.toggle_pin(r);
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("hide").clicked() {
+                                        hud.hide(r);
+                                        ui.close_menu();
+                                    }
+                                });
+                                ui.end_row();
+                            }
+                        });
+                    });
+            });
+        self.hud = hud;
+        for (r, rect) in marks {
+            self.tutorial.mark(Target::Resource(r), rect);
+        }
+    }
+}
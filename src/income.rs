@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+
+use crate::{
+    building::{BuildingType, Resource},
+    land::LandPricing,
+    market::base_price,
+    tile::{Pos, Tile},
+    resources::ResourceBag,
+    Data,
+};
+
+/// what the working buildings on some land add up to. each tile keeps its own, and the city keeps
+/// the total, so that a tick does not need to visit every tile
+#[derive(Clone, Default, PartialEq)]
+pub struct Income {
+    /// how many of each building are working
+    pub working: HashMap<BuildingType, i32>,
+    /// what the working buildings make each tick, including the storage they add
+    pub production: ResourceBag,
+    /// what the bank counts the land and everything built on it as worth, in tax
+    pub value: i64,
+    /// how many buildings are cut off from the road network
+    pub disconnected: i32,
+}
+
+impl Income {
+    /// work out the income of a tile from its buildings. `land_price` is what the land would cost now
+    pub fn of(tile: &Tile, land_price: i32) -> Income {
+        let working = tile.working();
+        let mut production = ResourceBag::default();
+        for (b, n) in &working {
+            production += ResourceBag::of(&b.output()) * *n as i64;
+        }
+        // every building counts, working or not, at the tax and market price of the goods it cost
+        let mut value = land_price as f32;
+        for (b, n) in &tile.buildings {
+            for (r, cost) in b.cost() {
+                let price = match r {
+                    Resource::Tax => 1.0,
+                    r => base_price(r).unwrap_or(0.0),
+                };
+                value += price * (cost * n) as f32;
+            }
+        }
+        Income {
+            working,
+            production,
+            value: value.round() as i64,
+            disconnected: tile.disconnected.len() as i32,
+        }
+    }
+
+    /// add another income to this one, or take it away if `sign` is -1
+    fn add(&mut self, other: &Income, sign: i32) {
+        for (b, n) in &other.working {
+            *self.working.entry(*b).or_insert(0) += n * sign;
+        }
+        // buildings that are all gone are left out, as they would be if the total was worked out from scratch
+        self.working.retain(|_, n| *n != 0);
+        self.production += other.production * sign as i64;
+        self.value += other.value * sign as i64;
+        self.disconnected += other.disconnected * sign;
+    }
+
+    /// how much of a resource is made each tick
+    pub fn made(&self, resource: Resource) -> i64 {
+        self.production.get(resource)
+    }
+
+    /// how many buildings of a type are working
+    pub fn count(&self, building_type: BuildingType) -> i32 {
+        *self.working.get(&building_type).unwrap_or(&0)
+    }
+}
+
+impl Data {
+    /// bring the income of a tile up to date after its buildings or roads changed, and add the
+    /// difference to the income of the city
+    pub fn refresh_income(&mut self, pos: Pos) {
+        let land_price = self.land_price(pos);
+        if let Some(tile) = self.tiles.get_mut(&pos) {
+            let new = Income::of(tile, land_price);
+            if new != tile.income {
+                self.income.add(&tile.income, -1);
+                self.income.add(&new, 1);
+                tile.income = new;
+            }
+        }
+    }
+
+    /// work out the income of every tile and of the city from scratch. this is needed after loading and
+    /// when the price of land changes, as every other change refreshes the tiles it touched
+    pub fn rebuild_income(&mut self) {
+        self.income = Income::default();
+        let prices = self.tiles.keys().map(|x| (*x, self.land_price(*x))).collect::<HashMap<_, _>>();
+        for (pos, tile) in self.tiles.iter_mut() {
+            tile.income = Income::of(tile, prices[pos]);
+            self.income.add(&tile.income, 1);
+        }
+        self.income_current = true;
+        self.land_basis = Some(self.land_basis());
+    }
+
+    /// what the price of land depends on: the number of tiles owned, the stages unlocked and the pricing
+    pub fn land_basis(&self) -> (usize, usize, LandPricing) {
+        (self.tiles.len(), self.stages_unlocked(), self.land_pricing.clone())
+    }
+}
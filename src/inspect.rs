@@ -0,0 +1,266 @@
+use std::collections::HashMap;
+
+use crate::{
+    building::{Building, BuildingType, Resource},
+    history::Action,
+    logistics::stockpile_capacity,
+    resources::ResourceBag,
+    tile::{Invalid, Pos, DISCONNECTED_COLOR},
+    Data,
+};
+
+/// write a list of resources like `🌾5 🪵10 `, or `nothing` if it is empty
+fn list_resources(resources: &ResourceBag) -> String {
+    let list = resources
+        .iter()
+        .map(|x| format!("{}{} ", x.0.symbol(), x.1))
+        .collect::<String>();
+    match list.is_empty() {
+        true => "nothing".to_owned(),
+        false => list,
+    }
+}
+
+/// list the buildings counted on a tile, most common first, leaving out empty ground
+fn list_buildings(ui: &mut egui::Ui, buildings: &HashMap<BuildingType, i32>) {
+    let mut buildings = buildings
+        .iter()
+        .filter(|x| x.0 != &BuildingType::Ground && x.1 > &0)
+        .collect::<Vec<_>>();
+    buildings.sort_by_key(|x| (-x.1, x.0.name()));
+    if buildings.is_empty() {
+        ui.small("no buildings");
+    }
+    for (b, n) in buildings {
+        ui.label(format!("{} {} x{}", b.symbol(), b.name(), n));
+    }
+}
+
+impl Data {
+    /// what removing the building on a world cell would give back. refunds are capped by storage
+    pub fn refund(&self, cell: Pos) -> ResourceBag {
+        let (tile, local) = cell.split_world_cell();
+        let building = match self.tiles.get(&tile) {
+            Some(t) => &t.land[local.x as usize][local.y as usize],
+            None => return ResourceBag::default(),
+        };
+        let funds = self.funds(tile);
+        let mut after = funds;
+        after.refund(&ResourceBag::of(&building.cost), funds.get(Resource::Storage));
+        after - funds
+    }
+
+    /// check if a building is in a stage that has been unlocked
+    fn unlocked(&self, building: &BuildingType) -> bool {
+        self.stage.iter().any(|x| x.enabled && x.buildings.contains(building))
+    }
+
+    /// the buildings from unlocked stages that could replace the one on a world cell without
+    /// leaving anything on the tile invalid
+    fn replacements(&self, cell: Pos) -> Vec<BuildingType> {
+        let (tile, local) = cell.split_world_cell();
+        let tile = match self.tiles.get(&tile) {
+            Some(t) => t,
+            None => return vec![],
+        };
+        let current = tile.land[local.x as usize][local.y as usize].building_type;
+        self.stage
+            .iter()
+            .filter(|x| x.enabled)
+            .flat_map(|x| x.buildings.iter().copied())
+            .filter(|b| b != &current && b != &BuildingType::Ground && Some(*b) != current.upgrade())
+            .filter(|b| {
+                matches!(
+                    tile.can_place(local, &Building::new(b), &ResourceBag::default()),
+                    Ok(_) | Err(Invalid::Unaffordable(_))
+                )
+            })
+            .collect()
+    }
+
+    /// swap the building on a world cell for another one in place, paying the difference in cost,
+    /// as a single action that can be undone
+    pub fn replace_building(&mut self, cell: Pos, building_type: BuildingType) -> Result<(), Invalid> {
+        let (tile_pos, local) = cell.split_world_cell();
+        let tile = match self.tiles.get(&tile_pos) {
+            Some(t) => t,
+            None => return Ok(()),
+        };
+        let building = Building::new(&building_type);
+        tile.can_place(local, &building, &self.funds(tile_pos))?;
+
+        let before = tile.snapshot();
+        let delta = self
+            .with_funds(tile_pos, |tile, funds| {
+                let old_funds = *funds;
+                tile.place(local, building, funds);
+                *funds - old_funds
+            })
+            .unwrap_or_default();
+        self.history.push(Action::Edit {
+            tile: tile_pos,
+            before: Box::new(before),
+            after: Box::new(self.tiles[&tile_pos].snapshot()),
+            resources: delta,
+        });
+        self.add_buildings(tile_pos.get_adjacent());
+        Ok(())
+    }
+
+    /// render the inspector for the cell picked with the inspect tool
+    pub fn render_inspector(&mut self, egui_ctx: &egui::Context) {
+        let cell = match self.input_settings.inspected {
+            Some(c) => c,
+            None => return,
+        };
+        let (tile_pos, local) = cell.split_world_cell();
+        let tile = match self.tiles.get(&tile_pos) {
+            Some(t) => t,
+            None => {
+                self.input_settings.inspected = None;
+                return;
+            }
+        };
+        // buildings that cover more than one cell are inspected from their anchor
+        let local = tile.anchor(local);
+        let cell = Pos::world_cell(tile_pos, local);
+        let building = &tile.land[local.x as usize][local.y as usize];
+        let building_type = building.building_type;
+        let count = *tile.buildings.get(&building_type).unwrap_or(&0);
+        let refund = self.refund(cell);
+        let replacements = self.replacements(cell);
+        let upgrade = building_type.upgrade().filter(|x| self.unlocked(x));
+
+        let mut replace = None;
+        let mut open = true;
+        egui::Window::new(format!("{} Inspector", egui_phosphor::MAGNIFYING_GLASS))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                ui.heading(format!("{} {}", building_type.symbol(), building_type.name()));
+                ui.small(format!("tile {} cell ({},{})", tile_pos.to_string(), local.y, local.x));
+                if let Some(b) = tile.planned.get(&local) {
+                    ui.small(format!("{} is planned here", b.name()));
+                }
+                if building_type == BuildingType::Ground {
+                    ui.label("there is nothing built here");
+                }
+                if building_type.is_multi_cell() {
+                    let (rows, cols) = building_type.footprint();
+                    ui.small(format!("covers {}x{} cells", cols, rows));
+                }
+
+                ui.separator();
+                egui::Grid::new("inspector economy").show(ui, |ui| {
+                    ui.label("produces");
+                    ui.label(format!("{}per tick", list_resources(&ResourceBag::of(&building_type.output()))));
+                    ui.end_row();
+                    // buildings only produce at the moment, nothing is used up while they run
+                    ui.label("consumes");
+                    ui.label("nothing");
+                    ui.end_row();
+                    if count > 1 {
+                        let total = ResourceBag::of(&building_type.output()) * count as i64;
+                        ui.label(format!("all {} on this tile", count));
+                        ui.label(format!("{}per tick", list_resources(&total)));
+                        ui.end_row();
+                    }
+                    ui.label("refund");
+                    ui.label(list_resources(&refund))
+                        .on_hover_text("what removing it gives back. refunds cannot go over your storage");
+                    ui.end_row();
+                });
+
+                if building_type != BuildingType::Ground {
+                    ui.separator();
+                    match tile.is_valid(local, building) {
+                        Ok(_) => ui.label(format!("{} placed correctly", egui_phosphor::CHECK)),
+                        Err(reason) => ui.colored_label(
+                            egui::Color32::from_rgb(200, 60, 60),
+                            format!("{} {}", egui_phosphor::WARNING, reason.describe()),
+                        ),
+                    };
+                    if self.is_disconnected(cell) {
+                        ui.colored_label(
+                            DISCONNECTED_COLOR,
+                            format!("{} {}", egui_phosphor::ROAD_HORIZON, self.road_hint(cell)),
+                        );
+                        ui.small("it will not produce anything until it is connected");
+                    }
+                    let neighbours = tile
+                        .neighbours(local, &building_type)
+                        .iter()
+                        .filter(|x| x.1 != BuildingType::Ground)
+                        .map(|x| format!("{} ", x.1.symbol()))
+                        .collect::<String>();
+                    ui.small(format!(
+                        "next to: {}",
+                        if neighbours.is_empty() { "nothing" } else { &neighbours }
+                    ));
+                    if !building.required_adj.is_empty() {
+                        ui.small(format!(
+                            "must be next to: {}",
+                            building.required_adj.iter().map(|x| format!("{} ", x.symbol())).collect::<String>()
+                        ));
+                    }
+                    if !building.tile_adj.is_empty() {
+                        ui.small(format!(
+                            "needs on the same tile: {}",
+                            building.tile_adj.iter().map(|x| format!("{} ", x.symbol())).collect::<String>()
+                        ));
+                    }
+                }
+
+                ui.separator();
+                ui.collapsing("this tile", |ui| list_buildings(ui, &tile.buildings));
+                ui.collapsing("this tile and its neighbours", |ui| list_buildings(ui, &tile.neighbors_buildings));
+                if self.logistics {
+                    ui.collapsing("stockpile", |ui| {
+                        ui.label(list_resources(&tile.stock));
+                        ui.small(format!("holds up to {} of each good", stockpile_capacity(tile)));
+                    });
+                }
+
+                if let Some(b) = upgrade {
+                    ui.separator();
+                    let cost = ResourceBag::of(&building_type.replace_cost(&b));
+                    let button = ui
+                        .button(format!("{} Upgrade to {} {}", egui_phosphor::ARROW_FAT_UP, b.symbol(), b.name()))
+                        .on_hover_text(format!("costs {}", list_resources(&cost)));
+                    if button.clicked() {
+                        replace = Some(b);
+                    }
+                    if let Err(reason) = tile.can_place(local, &Building::new(&b), &self.funds(tile_pos)) {
+                        ui.small(reason.describe());
+                    }
+                }
+
+                if building_type != BuildingType::Ground && !replacements.is_empty() {
+                    ui.separator();
+                    ui.label("Replace with");
+                    ui.horizontal_wrapped(|ui| {
+                        for b in &replacements {
+                            if ui
+                                .small_button(format!("{} {}", b.symbol(), b.name()))
+                                .on_hover_text(format!(
+                                    "costs {}. only the difference from the current building is paid",
+                                    list_resources(&ResourceBag::of(&building_type.replace_cost(b)))
+                                ))
+                                .clicked()
+                            {
+                                replace = Some(*b);
+                            }
+                        }
+                    });
+                }
+            });
+        if !open {
+            self.input_settings.inspected = None;
+        }
+        if let Some(b) = replace {
+            if let Err(reason) = self.replace_building(cell, b) {
+                self.info(reason);
+            }
+        }
+    }
+}
@@ -0,0 +1,540 @@
+use std::collections::HashMap;
+
+use egui::{Key, KeyboardShortcut, Modifiers};
+use strum::IntoEnumIterator;
+use strum_macros::{EnumIter, IntoStaticStr};
+
+use crate::{Building, Data, EditTool, SelectTool};
+
+/// how fast the keys move the land, in points per second
+const PAN_SPEED: f32 = 600.0;
+
+/// the keys that can be bound to an action. escape is left out so that it can always cancel rebinding
+const KEYS: [Key; 58] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K, Key::L, Key::M,
+    Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V, Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7, Key::Num8, Key::Num9,
+    Key::ArrowUp, Key::ArrowDown, Key::ArrowLeft, Key::ArrowRight,
+    Key::Minus, Key::PlusEquals, Key::Space, Key::Enter, Key::Tab, Key::Backspace, Key::Delete, Key::Insert,
+    Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F10,
+];
+
+/// something the player can do with the keyboard. the bindings are saved by the name of the action, so
+/// actions can be added without changing the settings file, but renaming one loses its keys
+#[derive(PartialEq, Eq, Hash, Copy, Clone, EnumIter, IntoStaticStr)]
+pub enum Action {
+    Building1,
+    Building2,
+    Building3,
+    Building4,
+    Building5,
+    Building6,
+    Building7,
+    Building8,
+    Building9,
+    NextStage,
+    PreviousStage,
+    PlanMode,
+    InspectMode,
+    RemoveTool,
+    PanUp,
+    PanDown,
+    PanLeft,
+    PanRight,
+    ZoomIn,
+    ZoomOut,
+    ResetZoom,
+    Undo,
+    Redo,
+    /// held to show the symbols of every building on the land
+    ShowSymbols,
+    /// held over a tile to show the button that copies it into a blueprint
+    CopyTile,
+    RotateBlueprint,
+    FlipBlueprint,
+    BuildMenu,
+    Blueprints,
+    BuildQueue,
+    RoadNetwork,
+    Logistics,
+    Statistics,
+    Land,
+    Market,
+    Bank,
+    Settings,
+}
+
+impl Action {
+    /// the name that the action's keys are saved under
+    fn id(&self) -> &'static str {
+        self.into()
+    }
+
+    pub fn name(&self) -> String {
+        match self.building_slot() {
+            Some(n) => format!("Building {}", n + 1),
+            None => (match self {
+                Action::NextStage => "Next stage",
+                Action::PreviousStage => "Previous stage",
+                Action::PlanMode => "Plan mode",
+                Action::InspectMode => "Inspect mode",
+                Action::RemoveTool => "Destroy tool",
+                Action::PanUp => "Move up",
+                Action::PanDown => "Move down",
+                Action::PanLeft => "Move left",
+                Action::PanRight => "Move right",
+                Action::ZoomIn => "Zoom in",
+                Action::ZoomOut => "Zoom out",
+                Action::ResetZoom => "Reset zoom",
+                Action::Undo => "Undo",
+                Action::Redo => "Redo",
+                Action::ShowSymbols => "Show symbols (hold)",
+                Action::CopyTile => "Copy tile (hold)",
+                Action::RotateBlueprint => "Rotate blueprint",
+                Action::FlipBlueprint => "Flip blueprint",
+                Action::BuildMenu => "Building menu",
+                Action::Blueprints => "Blueprints",
+                Action::BuildQueue => "Build queue",
+                Action::RoadNetwork => "Road network",
+                Action::Logistics => "Logistics",
+                Action::Statistics => "Statistics",
+                Action::Land => "Land",
+                Action::Market => "Market",
+                Action::Bank => "Bank",
+                _ => "Settings",
+            })
+            .to_owned(),
+        }
+    }
+
+    /// which building of the current stage the action picks, counting from 0
+    pub fn building_slot(&self) -> Option<usize> {
+        Some(match self {
+            Action::Building1 => 0,
+            Action::Building2 => 1,
+            Action::Building3 => 2,
+            Action::Building4 => 3,
+            Action::Building5 => 4,
+            Action::Building6 => 5,
+            Action::Building7 => 6,
+            Action::Building8 => 7,
+            Action::Building9 => 8,
+            _ => return None,
+        })
+    }
+
+    /// actions that happen for as long as their key is held, instead of once when it is pressed
+    fn held(&self) -> bool {
+        matches!(
+            self,
+            Action::PanUp | Action::PanDown | Action::PanLeft | Action::PanRight | Action::ShowSymbols | Action::CopyTile
+        )
+    }
+
+    /// the keys an action is bound to before the player changes them
+    fn default_bindings(&self) -> Vec<Binding> {
+        let key = Binding::key;
+        let shift = |k| Binding { shift: true, ..Binding::key(k) };
+        let command = |k| Binding { command: true, ..Binding::key(k) };
+        match self {
+            Action::Building1 => vec![key(Key::Num1)],
+            Action::Building2 => vec![key(Key::Num2)],
+            Action::Building3 => vec![key(Key::Num3)],
+            Action::Building4 => vec![key(Key::Num4)],
+            Action::Building5 => vec![key(Key::Num5)],
+            Action::Building6 => vec![key(Key::Num6)],
+            Action::Building7 => vec![key(Key::Num7)],
+            Action::Building8 => vec![key(Key::Num8)],
+            Action::Building9 => vec![key(Key::Num9)],
+            Action::NextStage => vec![key(Key::E)],
+            Action::PreviousStage => vec![shift(Key::E)],
+            Action::PlanMode => vec![key(Key::P)],
+            Action::InspectMode => vec![key(Key::I)],
+            Action::RemoveTool => vec![key(Key::X), key(Key::Delete)],
+            Action::PanUp => vec![key(Key::W), key(Key::ArrowUp)],
+            Action::PanDown => vec![key(Key::S), key(Key::ArrowDown)],
+            Action::PanLeft => vec![key(Key::A), key(Key::ArrowLeft)],
+            Action::PanRight => vec![key(Key::D), key(Key::ArrowRight)],
+            Action::ZoomIn => vec![key(Key::PlusEquals)],
+            Action::ZoomOut => vec![key(Key::Minus)],
+            Action::ResetZoom => vec![key(Key::Num0)],
+            Action::Undo => vec![command(Key::Z)],
+            Action::Redo => vec![command(Key::Y), Binding { shift: true, ..command(Key::Z) }],
+            Action::ShowSymbols => vec![key(Key::Q)],
+            Action::CopyTile => vec![key(Key::C)],
+            Action::RotateBlueprint => vec![key(Key::R)],
+            Action::FlipBlueprint => vec![key(Key::F)],
+            Action::BuildMenu => vec![key(Key::B)],
+            Action::Blueprints => vec![shift(Key::B)],
+            Action::BuildQueue => vec![shift(Key::P)],
+            Action::RoadNetwork => vec![shift(Key::R)],
+            Action::Logistics => vec![shift(Key::L)],
+            Action::Statistics => vec![shift(Key::S)],
+            Action::Land => vec![key(Key::L)],
+            Action::Market => vec![key(Key::M)],
+            Action::Bank => vec![shift(Key::M)],
+            Action::Settings => vec![key(Key::F10)],
+        }
+    }
+}
+
+/// a key and the modifiers that have to be held with it
+#[derive(Savefile, Clone, PartialEq)]
+pub struct Binding {
+    /// the name of the key, as given by `Key::name`. a name is kept instead of the key so that the
+    /// settings file does not depend on the order of the keys in egui
+    key: String,
+    /// ctrl, or cmd on mac
+    command: bool,
+    shift: bool,
+}
+
+impl Binding {
+    fn key(key: Key) -> Binding {
+        Binding {
+            key: key.name().to_owned(),
+            command: false,
+            shift: false,
+        }
+    }
+
+    fn shortcut(&self) -> Option<KeyboardShortcut> {
+        let key = *KEYS.iter().find(|x| x.name() == self.key)?;
+        let mut modifiers = Modifiers::NONE;
+        if self.command {
+            modifiers = modifiers | Modifiers::COMMAND;
+        }
+        if self.shift {
+            modifiers = modifiers | Modifiers::SHIFT;
+        }
+        Some(KeyboardShortcut { modifiers, key })
+    }
+
+    /// check if the modifiers being held are the ones the binding needs
+    fn modifiers_match(&self, input: &egui::InputState) -> bool {
+        input.modifiers.command == self.command && input.modifiers.shift == self.shift
+    }
+
+    fn pressed(&self, input: &egui::InputState) -> bool {
+        match self.shortcut() {
+            Some(s) => self.modifiers_match(input) && input.key_pressed(s.key),
+            None => false,
+        }
+    }
+
+    fn down(&self, input: &egui::InputState) -> bool {
+        match self.shortcut() {
+            Some(s) => self.modifiers_match(input) && input.key_down(s.key),
+            None => false,
+        }
+    }
+
+    pub fn text(&self, egui_ctx: &egui::Context) -> String {
+        match self.shortcut() {
+            Some(s) => egui_ctx.format_shortcut(&s),
+            None => self.key.clone(),
+        }
+    }
+}
+
+/// the actions as version 1 of the settings file saved them, which is only used to read those files.
+/// savefile checks the names of the variants, so this must not be changed
+#[derive(PartialEq, Eq, Hash, Copy, Clone, EnumIter, IntoStaticStr, Savefile)]
+enum SavedAction {
+    Building1, Building2, Building3, Building4, Building5, Building6, Building7, Building8, Building9,
+    NextStage, PreviousStage, PlanMode, InspectMode, RemoveTool, PanUp, PanDown, PanLeft, PanRight,
+    ZoomIn, ZoomOut, ResetZoom, Undo, Redo, ShowSymbols, CopyTile, RotateBlueprint, FlipBlueprint,
+    BuildMenu, Blueprints, BuildQueue, RoadNetwork, Logistics, Statistics, Land, Market, Bank, Settings,
+}
+
+/// the keys bound to each action. saved in the settings file
+#[derive(Savefile, Clone)]
+pub struct Bindings {
+    /// the bindings by the name of the action, as given by `Action::id`
+    #[savefile_versions = "2.."]
+    keys: HashMap<String, Vec<Binding>>,
+    /// the bindings from version 1 of the settings file, which saved the actions themselves. they are
+    /// moved into `keys` when the settings are loaded
+    #[savefile_versions = "0..1"]
+    #[savefile_default_fn = "no_old_keys"]
+    old_keys: HashMap<SavedAction, Vec<Binding>>,
+    /// the action and which of its slots is waiting for a key to be pressed
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    rebinding: Option<(Action, usize)>,
+}
+
+/// files from after version 1 do not have `old_keys`, which savefile needs to be told
+fn no_old_keys() -> HashMap<SavedAction, Vec<Binding>> {
+    HashMap::new()
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Bindings {
+            keys: Action::iter().map(|x| (x.id().to_owned(), x.default_bindings())).collect(),
+            old_keys: HashMap::new(),
+            rebinding: None,
+        }
+    }
+}
+
+impl Bindings {
+    /// move the bindings read from an older settings file into `keys`
+    pub fn upgrade(&mut self) {
+        for (action, bindings) in std::mem::take(&mut self.old_keys) {
+            let id: &'static str = action.into();
+            self.keys.insert(id.to_owned(), bindings);
+        }
+    }
+
+    /// the keys bound to an action. actions added after the settings were saved get their default keys
+    fn of(&self, action: Action) -> Vec<Binding> {
+        match self.keys.get(action.id()) {
+            Some(b) => b.clone(),
+            None => action.default_bindings(),
+        }
+    }
+
+    /// a name followed by the first key bound to an action, for showing in hover text
+    pub fn hint(&self, egui_ctx: &egui::Context, name: &str, action: Action) -> String {
+        match self.of(action).first() {
+            Some(b) => format!("{} ({})", name, b.text(egui_ctx)),
+            None => name.to_owned(),
+        }
+    }
+
+    /// the actions whose keys were pressed this frame, or are being held for actions that last while
+    /// held. nothing is returned while typing in a text box or picking a key to bind
+    pub fn actions(&self, egui_ctx: &egui::Context) -> Vec<Action> {
+        if egui_ctx.wants_keyboard_input() || self.rebinding.is_some() {
+            return vec![];
+        }
+        egui_ctx.input(|i| {
+            Action::iter()
+                .filter(|a| {
+                    self.of(*a).iter().any(|b| match a.held() {
+                        true => b.down(i),
+                        false => b.pressed(i),
+                    })
+                })
+                .collect()
+        })
+    }
+
+    /// render the list of actions with the keys bound to them. click a key to change it and right
+    /// click to clear it. returns true if a binding was changed
+    pub fn render(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        if let Some((action, slot)) = self.rebinding {
+            let pressed = ui.input(|i| {
+                i.events.iter().find_map(|e| match e {
+                    egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+                    _ => None,
+                })
+            });
+            match pressed {
+                Some((Key::Escape, _)) => self.rebinding = None,
+                Some((key, modifiers)) if KEYS.contains(&key) => {
+                    let mut bindings = self.of(action);
+                    let binding = Binding {
+                        key: key.name().to_owned(),
+                        command: modifiers.command,
+                        shift: modifiers.shift,
+                    };
+                    match bindings.get_mut(slot) {
+                        Some(b) => *b = binding,
+                        None => bindings.push(binding),
+                    }
+                    self.keys.insert(action.id().to_owned(), bindings);
+                    self.rebinding = None;
+                    changed = true;
+                }
+                _ => {}
+            }
+        }
+
+        egui::Grid::new("key bindings").striped(true).show(ui, |ui| {
+            for action in Action::iter() {
+                ui.label(action.name());
+                let bindings = self.of(action);
+                for slot in 0..2 {
+                    let text = match (self.rebinding == Some((action, slot)), bindings.get(slot)) {
+                        (true, _) => "press a key".to_owned(),
+                        (false, Some(b)) => b.text(ui.ctx()),
+                        (false, None) => "-".to_owned(),
+                    };
+                    let response = ui.button(text);
+                    if response.clicked() {
+                        self.rebinding = Some((action, slot.min(bindings.len())));
+                    }
+                    if response.secondary_clicked() && slot < bindings.len() {
+                        let mut bindings = bindings.clone();
+                        bindings.remove(slot);
+                        self.keys.insert(action.id().to_owned(), bindings);
+                        changed = true;
+                    }
+                }
+                ui.end_row();
+            }
+        });
+        if self.rebinding.is_some() {
+            ui.small("press the key to bind, or escape to cancel");
+        }
+        ui.horizontal(|ui| {
+            ui.small("click a key to change it and right click to clear it");
+            if ui.small_button("Reset").clicked() {
+                *self = Bindings::default();
+                changed = true;
+            }
+        });
+        changed
+    }
+}
+
+impl Data {
+    /// do an action that only changes the city. the actions that open windows outside of the city
+    /// are handled by the main loop
+    pub fn run_action(&mut self, action: Action, egui_ctx: &egui::Context) {
+        if let Some(slot) = action.building_slot() {
+            let stage = &self.stage[self.input_settings.stage.min(self.stage.len() - 1)];
+            if let Some(b) = stage.buildings.get(slot).filter(|_| stage.enabled) {
+                self.input_settings.edit_tool = EditTool::Build(Building::new(b));
+                if self.input_settings.select_tool == SelectTool::Inspect {
+                    self.input_settings.select_tool = SelectTool::Add;
+                }
+            }
+            return;
+        }
+        let dt = egui_ctx.input(|i| i.stable_dt).min(0.1);
+        let center = egui_ctx.screen_rect().center();
+        match action {
+            Action::NextStage => self.cycle_stage(1),
+            Action::PreviousStage => self.cycle_stage(-1),
+            Action::PlanMode => {
+                self.input_settings.select_tool = match self.input_settings.select_tool {
+                    SelectTool::Plan => SelectTool::Add,
+                    _ => SelectTool::Plan,
+                }
+            }
+            Action::InspectMode => {
+                self.input_settings.select_tool = match self.input_settings.select_tool {
+                    SelectTool::Inspect => SelectTool::Add,
+                    _ => SelectTool::Inspect,
+                }
+            }
+            Action::RemoveTool => self.input_settings.edit_tool = EditTool::Remove,
+            Action::PanUp => self.screen_offset.1 += PAN_SPEED * dt,
+            Action::PanDown => self.screen_offset.1 -= PAN_SPEED * dt,
+            Action::PanLeft => self.screen_offset.0 += PAN_SPEED * dt,
+            Action::PanRight => self.screen_offset.0 -= PAN_SPEED * dt,
+            Action::ZoomIn => self.zoom(1.25, center),
+            Action::ZoomOut => self.zoom(0.8, center),
+            Action::ResetZoom => self.zoom(1.0 / self.view().scale, center),
+            Action::Undo => {
+                self.undo();
+            }
+            Action::Redo => {
+                self.redo();
+            }
+            Action::ShowSymbols => self.input_settings.show_symbols = true,
+            Action::CopyTile => self.input_settings.copy_tile = true,
+            Action::RotateBlueprint | Action::FlipBlueprint => {
+                if let EditTool::Blueprint(b) = &mut self.input_settings.edit_tool {
+                    *b = match action {
+                        Action::RotateBlueprint => b.rotated(),
+                        _ => b.mirrored(),
+                    };
+                }
+            }
+            Action::BuildMenu => self.popup = !self.popup,
+            Action::BuildQueue => self.queue_open = !self.queue_open,
+            Action::RoadNetwork => self.input_settings.road_overlay = !self.input_settings.road_overlay,
+            Action::Logistics => self.logistics_open = !self.logistics_open,
+            Action::Statistics => self.stats.open = !self.stats.open,
+            Action::Land => self.land_open = !self.land_open,
+            Action::Market => self.market.open = !self.market.open,
+            Action::Bank => self.loan.open = !self.loan.open,
+            _ => {}
+        }
+    }
+
+    /// move the number keys on to the next stage that is unlocked, going backwards if `step` is -1
+    fn cycle_stage(&mut self, step: i32) {
+        let n = self.stage.len() as i32;
+        let mut i = self.input_settings.stage as i32;
+        for _ in 0..n {
+            i = (i + step).rem_euclid(n);
+            if self.stage[i as usize].enabled {
+                self.input_settings.stage = i as usize;
+                return;
+            }
+        }
+    }
+
+    /// render the buildings of the current stage with the number that picks each of them
+    pub fn render_hotbar(&mut self, egui_ctx: &egui::Context, bindings: &Bindings) {
+        let index = self.input_settings.stage.min(self.stage.len() - 1);
+        if !self.stage[index].enabled {
+            return;
+        }
+        let mut picked = None;
+        egui::Area::new("hotbar")
+            .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+            .order(egui::Order::Foreground)
+            .show(egui_ctx, |ui| {
+                egui::Frame::group(ui.style()).fill(ui.visuals().window_fill()).show(ui, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.small_button(egui_phosphor::CARET_LEFT).on_hover_text(bindings.hint(egui_ctx, "Previous stage", Action::PreviousStage)).clicked() {
+                            self.cycle_stage(-1);
+                        }
+                        ui.label(format!("Stage {}", self.stage[index].num));
+                        if ui.small_button(egui_phosphor::CARET_RIGHT).on_hover_text(bindings.hint(egui_ctx, "Next stage", Action::NextStage)).clicked() {
+                            self.cycle_stage(1);
+                        }
+                        let slots = Action::iter().filter_map(|a| a.building_slot().map(|s| (a, s)));
+                        for ((action, _), b) in slots.zip(&self.stage[index].buildings) {
+                            let selected = match &self.input_settings.edit_tool {
+                                EditTool::Build(x) => x.building_type == *b,
+                                _ => false,
+                            };
+                            if ui
+                                .selectable_label(selected, b.symbol())
+                                .on_hover_text(bindings.hint(egui_ctx, &b.name(), action))
+                                .clicked()
+                            {
+                                picked = Some(action);
+                            }
+                        }
+                    });
+                });
+            });
+        if let Some(action) = picked {
+            self.run_action(action, egui_ctx);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saved_actions_match_the_actions() {
+        // every action from version 1 still has its name, so its keys are not lost
+        for action in SavedAction::iter() {
+            let id: &'static str = action.into();
+            assert!(Action::iter().any(|x| x.id() == id));
+        }
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        let mut bindings = Bindings::default();
+        bindings.keys.insert(Action::Bank.id().to_owned(), vec![Binding::key(Key::K)]);
+        let bytes = savefile::save_to_mem(2, &bindings).unwrap();
+        let loaded: Bindings = savefile::load_from_mem(&bytes, 2).unwrap();
+        assert!(loaded.of(Action::Bank) == vec![Binding::key(Key::K)]);
+    }
+}
@@ -0,0 +1,368 @@
+use std::collections::{HashSet, VecDeque};
+
+use egui::{Color32, Frame, RichText, Vec2};
+
+use crate::{
+    building::{BuildingType, Resource},
+    history::Action,
+    loan,
+    tile::{Pos, Tile},
+    Data,
+};
+
+const AFFORDABLE_COLOR: Color32 = Color32::from_rgb(40, 140, 60);
+const BORROW_COLOR: Color32 = Color32::from_rgb(200, 130, 30);
+const UNAFFORDABLE_COLOR: Color32 = Color32::from_rgb(200, 60, 60);
+
+/// how the price of land is worked out. each city keeps its own so that it can be tuned per game
+#[derive(Savefile, Clone, PartialEq)]
+pub struct LandPricing {
+    /// the price of land next to the start, before anything else changes it
+    pub base: i32,
+    /// how quickly land gets more expensive further from the start. the price grows with the
+    /// distance to this power
+    pub distance_power: i32,
+    /// how much more land costs for every tile already owned, as a share of the price
+    pub per_owned: f32,
+    /// how much the terrain changes the price. 0 ignores it and 1 uses it fully, which makes land
+    /// from 20% cheaper to 30% dearer than the distance alone
+    pub terrain_weight: f32,
+    /// how much cheaper land is for every stage unlocked after the first, as a share of the price
+    pub research_discount: f32,
+    /// the share of the price that is given back when land is sold
+    pub sell_back: f32,
+}
+
+impl Default for LandPricing {
+    /// the defaults start from the old price of `(distance + 1)^3 * 100`. terrain then moves it by -20% to +30%,
+    /// each tile already owned adds 2% and each stage unlocked after the first takes off 5%, so land
+    /// rarely costs exactly what it used to
+    fn default() -> Self {
+        LandPricing {
+            base: 100,
+            distance_power: 3,
+            per_owned: 0.02,
+            terrain_weight: 1.0,
+            research_discount: 0.05,
+            sell_back: 0.5,
+        }
+    }
+}
+
+/// how good the land on a tile is, from 0.8 to 1.3. better land costs more. it is worked out from
+/// the position so that a tile always has the same terrain
+pub fn terrain_value(pos: Pos) -> f32 {
+    let hash = (pos.x.wrapping_mul(73856093) ^ pos.y.wrapping_mul(19349663)) as u32;
+    0.8 + (hash % 51) as f32 / 100.0
+}
+
+/// a word for how good the terrain is
+fn terrain_name(value: f32) -> &'static str {
+    match value {
+        v if v < 0.9 => "poor",
+        v if v < 1.1 => "average",
+        _ => "rich",
+    }
+}
+
+/// write a share as a percentage with a sign, like `+20%`
+fn percent(share: f32) -> String {
+    format!("{:+}%", (share * 100.0).round())
+}
+
+impl LandPricing {
+    /// the parts that make up the price of land: the price from its distance, and the share added
+    /// for terrain, owned land and research
+    fn parts(&self, pos: Pos, owned: usize, stages: usize) -> (f32, f32, f32, f32) {
+        let distance = pos.x.abs().max(pos.y.abs()) + 1;
+        let base = self.base as f32 * (distance as f32).powi(self.distance_power);
+        let terrain = self.terrain_weight * (terrain_value(pos) - 1.0);
+        let owned = self.per_owned * owned.saturating_sub(1) as f32;
+        let research = -self.research_discount * stages.saturating_sub(1) as f32;
+        (base, terrain, owned, research)
+    }
+
+    /// the price of land with `owned` tiles already owned and `stages` stages unlocked. it never
+    /// drops below half of the price from distance alone
+    pub fn price(&self, pos: Pos, owned: usize, stages: usize) -> i32 {
+        let (base, terrain, owned, research) = self.parts(pos, owned, stages);
+        (base * (1.0 + terrain + owned + research).max(0.5)).round() as i32
+    }
+
+    /// explain how the price of land was worked out
+    pub fn describe(&self, pos: Pos, owned: usize, stages: usize) -> String {
+        let (base, terrain, owned_share, research) = self.parts(pos, owned, stages);
+        format!(
+            "${} for the distance\n{} terrain {}\n{} owned tiles {}\nresearch {}",
+            base.round(),
+            terrain_name(terrain_value(pos)),
+            percent(terrain),
+            owned,
+            percent(owned_share),
+            percent(research)
+        )
+    }
+}
+
+impl Data {
+    /// the number of stages that have been unlocked
+    pub fn stages_unlocked(&self) -> usize {
+        self.stage.iter().filter(|x| x.enabled).count()
+    }
+
+    /// what a piece of land costs to buy now
+    pub fn land_price(&self, pos: Pos) -> i32 {
+        self.land_pricing.price(pos, self.tiles.len(), self.stages_unlocked())
+    }
+
+    /// check if a piece of owned land has no buildings, plans or goods on it
+    fn is_empty_land(&self, pos: Pos) -> bool {
+        self.tiles.get(&pos).is_some_and(|tile| {
+            tile.land.iter().flatten().all(|b| b.building_type == BuildingType::Ground)
+                && tile.planned.is_empty()
+                && tile.stock.is_empty()
+        })
+    }
+
+    /// check if taking a piece of land away would leave the rest in more than one part. the search starts
+    /// from one of the land's neighbours and stops once it has found the others, so it is usually quick
+    fn splits_land(&self, pos: Pos) -> bool {
+        let neighbours = pos.get_adjacent()[0..4]
+            .iter()
+            .copied()
+            .filter(|x| self.tiles.contains_key(x))
+            .collect::<Vec<_>>();
+        let Some(start) = neighbours.first() else {
+            return false;
+        };
+        let mut left = neighbours[1..].iter().copied().collect::<HashSet<_>>();
+        let mut seen = HashSet::from([pos, *start]);
+        let mut queue = VecDeque::from([*start]);
+        while let Some(p) = queue.pop_front() {
+            if left.is_empty() {
+                return false;
+            }
+            for next in &p.get_adjacent()[0..4] {
+                if self.tiles.contains_key(next) && seen.insert(*next) {
+                    left.remove(next);
+                    queue.push_back(*next);
+                }
+            }
+        }
+        !left.is_empty()
+    }
+
+    /// what an owned piece of land would sell for, or why it cannot be sold
+    pub fn sell_price(&self, pos: Pos) -> Result<i32, String> {
+        if !self.tiles.contains_key(&pos) {
+            return Err("the land is not owned".to_owned());
+        }
+        if pos == Pos::new(0, 0) || self.tiles.len() <= 1 {
+            return Err("the first piece of land cannot be sold".to_owned());
+        }
+        if !self.is_empty_land(pos) {
+            return Err("only empty land can be sold. remove its buildings, plans and goods first".to_owned());
+        }
+        if self.splits_land(pos) {
+            return Err("selling this land would split the city in two, cutting off the land and roads on either side".to_owned());
+        }
+        // the price of the land as if it was the last one bought
+        let price = self.land_pricing.price(pos, self.tiles.len() - 1, self.stages_unlocked());
+        Ok((price as f32 * self.land_pricing.sell_back) as i32)
+    }
+
+    /// sell a piece of empty land back for part of its price, as an action that can be undone
+    pub fn sell_land(&mut self, pos: Pos) -> Result<(), String> {
+        let refund = self.sell_price(pos)?;
+        self.tiles.remove(&pos);
+        self.resources.add(Resource::Tax, refund as i64);
+        self.history.push(Action::SellLand { pos, refund });
+        self.update_new_pos();
+        for p in pos.get_adjacent() {
+            self.add_buildings(p.get_adjacent());
+        }
+        Ok(())
+    }
+
+    /// draw the price over every piece of land that can be bought. land that cannot be afforded yet is
+    /// still shown so that the player can see what is coming, and land that needs a loan is marked
+    pub fn render_land_prices(&mut self, egui_ctx: &egui::Context) {
+        let mut add: Vec<(Pos, i32, i32)> = vec![];
+        let tax = self.resources.get(Resource::Tax);
+        let credit = self.available_credit();
+        let stages = self.stages_unlocked();
+        let view = self.view();
+        let screen = egui_ctx.screen_rect();
+        for i in &self.new_pos {
+            // only the land on the screen gets a window, so a big city does not make one for every piece around it
+            if !view.tile_rect(*i).intersects(screen) {
+                continue;
+            }
+            let price = self.land_price(*i);
+            // land that costs more than the tax there is can be bought with a loan from the bank
+            let borrow = (price as i64 - tax).max(0) as i32;
+            egui::Window::new(i.to_string())
+                .title_bar(false)
+                .resizable(false)
+                .collapsible(false)
+                .frame(Frame::none())
+                .fixed_rect({
+                    // the price sits on the side of the land nearest to the city
+                    let rect = view.tile_rect(*i);
+                    let shift = Vec2::new(
+                        match i.x < 0 {
+                            true => rect.width() / 2.0,
+                            false => 0.0,
+                        },
+                        match i.y < 0 {
+                            true => rect.height() - 22.0,
+                            false => 0.0,
+                        },
+                    );
+                    rect.translate(shift)
+                })
+                .show(egui_ctx, |ui| {
+                    let (label, color) = match borrow {
+                        0 => (format!("${}", price), AFFORDABLE_COLOR),
+                        n if n <= credit => (format!("${} ({} borrowed)", price, n), BORROW_COLOR),
+                        n => (format!("${} (need {} more)", price, n), UNAFFORDABLE_COLOR),
+                    };
+                    let response = ui
+                        .add_enabled(
+                            borrow <= credit,
+                            egui::Button::new(RichText::new(label).color(color)),
+                        )
+                        .on_hover_text(self.land_pricing.describe(*i, self.tiles.len(), stages))
+                        .on_disabled_hover_text(self.land_pricing.describe(*i, self.tiles.len(), stages));
+                    if response.clicked() {
+                        add.push((*i, price, borrow));
+                    }
+                });
+        }
+        for (i, price, borrow) in add {
+            if borrow > 0 {
+                self.take_loan(borrow, loan::DEFAULT_TERM);
+            }
+            self.resources.add(Resource::Tax, (borrow - price) as i64);
+            self.tiles.insert(i, Tile::new(i));
+            self.history.push(Action::BuyLand { pos: i, cost: price, borrowed: borrow });
+            self.update_new_pos();
+        }
+    }
+
+    /// render the land window, with the land that can be sold and the settings for land prices
+    pub fn render_land(&mut self, egui_ctx: &egui::Context) {
+        if !self.land_open {
+            return;
+        }
+        let mut sell = None;
+        let mut open = self.land_open;
+        let mut pricing = self.land_pricing.clone();
+        egui::Window::new(format!("{} Land", egui_phosphor::MAP_TRIFOLD))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                ui.label(format!("{} pieces of land owned", self.tiles.len()));
+                let cheapest = self.new_pos.iter().map(|x| self.land_price(*x)).min();
+                if let Some(p) = cheapest {
+                    ui.small(format!("the cheapest land for sale costs ${}", p));
+                }
+
+                ui.separator();
+                // empty land is listed even when it cannot be sold, so that the reason can be shown
+                let mut sellable = self
+                    .tiles
+                    .keys()
+                    .filter(|x| **x != Pos::new(0, 0) && self.is_empty_land(**x))
+                    .map(|x| (*x, self.sell_price(*x)))
+                    .collect::<Vec<_>>();
+                sellable.sort_by_key(|x| (x.0.y, x.0.x));
+                if sellable.is_empty() {
+                    ui.small("land can be sold back once it is empty");
+                }
+                for (pos, refund) in sellable {
+                    ui.horizontal(|ui| {
+                        ui.label(pos.to_string());
+                        match refund {
+                            Ok(refund) => {
+                                if ui.small_button(format!("sell for ${}", refund)).clicked() {
+                                    sell = Some(pos);
+                                }
+                            }
+                            Err(e) => {
+                                ui.add_enabled(false, egui::Button::new("sell").small()).on_disabled_hover_text(e);
+                            }
+                        }
+                    });
+                }
+
+                ui.separator();
+                ui.collapsing("pricing", |ui| {
+                    egui::Grid::new("land pricing").show(ui, |ui| {
+                        ui.label("base price");
+                        ui.add(egui::DragValue::new(&mut pricing.base).clamp_range(0..=10000));
+                        ui.end_row();
+                        ui.label("distance power");
+                        ui.add(egui::DragValue::new(&mut pricing.distance_power).clamp_range(0..=5));
+                        ui.end_row();
+                        ui.label("per owned tile");
+                        ui.add(egui::Slider::new(&mut pricing.per_owned, 0.0..=0.2));
+                        ui.end_row();
+                        ui.label("terrain");
+                        ui.add(egui::Slider::new(&mut pricing.terrain_weight, 0.0..=2.0));
+                        ui.end_row();
+                        ui.label("research discount");
+                        ui.add(egui::Slider::new(&mut pricing.research_discount, 0.0..=0.2));
+                        ui.end_row();
+                        ui.label("sell back");
+                        ui.add(egui::Slider::new(&mut pricing.sell_back, 0.0..=1.0));
+                        ui.end_row();
+                    });
+                    if ui.small_button("reset").clicked() {
+                        pricing = LandPricing::default();
+                    }
+                });
+            });
+        self.land_open = open;
+        self.land_pricing = pricing;
+        if let Some(pos) = sell {
+            if let Err(e) = self.sell_land(pos) {
+                self.info(e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a city with land at (0,0), and a row of land to its right with one piece above the middle
+    fn city() -> Data {
+        let mut data = Data::new("test".to_owned());
+        for pos in [Pos::new(1, 0), Pos::new(2, 0), Pos::new(1, -1)] {
+            data.tiles.insert(pos, Tile::new(pos));
+        }
+        data
+    }
+
+    #[test]
+    fn land_at_the_edge_can_be_sold() {
+        let data = city();
+        assert!(data.sell_price(Pos::new(2, 0)).is_ok());
+        assert!(data.sell_price(Pos::new(1, -1)).is_ok());
+    }
+
+    #[test]
+    fn land_that_joins_the_city_cannot_be_sold() {
+        let mut data = city();
+        assert!(data.splits_land(Pos::new(1, 0)));
+        assert!(data.sell_price(Pos::new(1, 0)).is_err());
+        // once there is another way around, it can be
+        for pos in [Pos::new(0, -1), Pos::new(2, -1)] {
+            data.tiles.insert(pos, Tile::new(pos));
+        }
+        assert!(!data.splits_land(Pos::new(1, 0)));
+        assert!(data.sell_price(Pos::new(1, 0)).is_ok());
+    }
+}
@@ -0,0 +1,384 @@
+use strum::IntoEnumIterator;
+
+use crate::{
+    building::{BuildingType, Resource},
+    saves::format_duration,
+    Data, TICK_SECS,
+};
+
+/// how much can be borrowed before the city is worth anything, so that loans help with the first land
+const STARTER_CREDIT: i32 = 500;
+/// the share of the city's value that can be borrowed against, which every bank adds to again
+const VALUE_SHARE: f32 = 0.25;
+/// the interest added to a loan every tick
+const INTEREST_PER_TICK: f32 = 0.001;
+/// the number of ticks between payments
+const PAYMENT_TICKS: i32 = 20;
+/// the extra added to a loan when a payment is missed, as a share of the payment
+const LATE_FEE: f32 = 0.1;
+/// the bank takes goods to cover the payment after this many payments are missed in a row
+const SEIZE_AFTER: i32 = 3;
+/// the number of payments that a loan is paid back over unless the player picks another
+pub const DEFAULT_TERM: i32 = 10;
+
+/// the tax that the bank has lent to the city and how it is being paid back
+#[derive(Savefile)]
+pub struct Loan {
+    /// how much tax is owed, including interest
+    pub owed: f32,
+    /// the number of payments left to pay it back
+    pub payments_left: i32,
+    /// the number of ticks until the next payment
+    pub next_payment: i32,
+    /// the number of payments missed in a row
+    pub missed: i32,
+    #[savefile_ignore]
+    pub open: bool,
+    /// the amount to borrow or repay with the buttons in the bank window
+    #[savefile_ignore]
+    #[savefile_default_val = "100"]
+    amount: i32,
+    /// the number of payments for a new loan
+    #[savefile_ignore]
+    #[savefile_default_fn = "default_term"]
+    term: i32,
+}
+
+/// the fields that are not saved have to be given their defaults when a city is loaded, or they would be 0
+fn default_term() -> i32 {
+    DEFAULT_TERM
+}
+
+impl Default for Loan {
+    fn default() -> Self {
+        Loan {
+            owed: 0.0,
+            payments_left: 0,
+            next_payment: 0,
+            missed: 0,
+            open: false,
+            amount: 100,
+            term: DEFAULT_TERM,
+        }
+    }
+}
+
+impl Loan {
+    /// the amount of the next payment
+    pub fn installment(&self) -> i32 {
+        (self.owed / self.payments_left.max(1) as f32).ceil() as i32
+    }
+
+    /// the payments that are left if every one is made on time, as the number of ticks until each
+    /// one along with its amount
+    pub fn schedule(&self) -> Vec<(i32, i32)> {
+        let mut owed = self.owed;
+        let mut ticks = self.next_payment;
+        let mut schedule = vec![];
+        for left in (1..=self.payments_left).rev() {
+            let payment = (owed / left as f32).ceil();
+            schedule.push((ticks, payment as i32));
+            owed = (owed - payment).max(0.0) * (1.0 + INTEREST_PER_TICK).powi(PAYMENT_TICKS);
+            ticks += PAYMENT_TICKS;
+        }
+        schedule
+    }
+
+    fn clear(&mut self) {
+        self.owed = 0.0;
+        self.payments_left = 0;
+        self.next_payment = 0;
+        self.missed = 0;
+    }
+}
+
+impl Data {
+    /// what the city is worth to the bank: the price of its land plus the tax and goods spent on its buildings.
+    /// each tile works out its own when it changes, so this is just the total
+    pub fn city_value(&self) -> i32 {
+        self.income.value as i32
+    }
+
+    /// the most that can be owed to the bank at once. every bank lends more against the city's value
+    pub fn credit_limit(&self) -> i32 {
+        let banks = self.income.count(BuildingType::Bank);
+        STARTER_CREDIT + (self.city_value() as f32 * VALUE_SHARE * (1 + banks) as f32) as i32
+    }
+
+    /// how much more can be borrowed. nothing can be borrowed while a payment is overdue
+    pub fn available_credit(&self) -> i32 {
+        match self.loan.missed {
+            0 => (self.credit_limit() - self.loan.owed.ceil() as i32).max(0),
+            _ => 0,
+        }
+    }
+
+    /// add to the loan without paying anything out. used when the tax is spent straight away, such as on land
+    pub fn take_loan(&mut self, amount: i32, term: i32) {
+        if self.loan.owed <= 0.0 {
+            self.loan.next_payment = PAYMENT_TICKS;
+        }
+        self.loan.owed += amount as f32;
+        // a loan is always paid back over at least one payment
+        self.loan.payments_left = self.loan.payments_left.max(term).max(1);
+    }
+
+    /// take part of the loan back off what is owed without paying for it. used when land that was bought
+    /// on credit is given back
+    pub fn forgive_loan(&mut self, amount: i32) {
+        self.loan.owed -= (amount as f32).min(self.loan.owed);
+        if self.loan.owed < 1.0 {
+            self.loan.clear();
+        }
+    }
+
+    /// borrow tax from the bank, paid back over `term` payments
+    pub fn borrow(&mut self, amount: i32, term: i32) -> Result<(), String> {
+        if self.loan.missed > 0 {
+            return Err("the bank will not lend any more until the missed payments are made".to_owned());
+        }
+        if amount > self.available_credit() {
+            return Err(format!("the bank will only lend {} more", self.available_credit()));
+        }
+        let (storage, cash_storage) = self.storage_caps();
+        let tax = self.resources.get(Resource::Tax);
+        if tax + amount as i64 > storage + cash_storage {
+            return Err(format!("there is only room to store {} more tax", (storage + cash_storage - tax).max(0)));
+        }
+        self.take_loan(amount, term);
+        self.resources.add(Resource::Tax, amount as i64);
+        Ok(())
+    }
+
+    /// pay back part of the loan early
+    pub fn repay(&mut self, amount: i32) {
+        let tax = self.resources.get(Resource::Tax);
+        let amount = (amount as i64).min(tax).min(self.loan.owed.ceil() as i64);
+        self.resources.add(Resource::Tax, -amount);
+        self.loan.owed -= amount as f32;
+        if self.loan.owed < 1.0 {
+            self.loan.clear();
+        }
+    }
+
+    /// the loan part of an economy tick. interest is added, and when a payment is due it is taken from the tax.
+    /// a missed payment adds a late fee and stops any more borrowing, and after several the bank takes goods instead
+    pub fn loan_tick(&mut self) {
+        if self.loan.owed <= 0.0 {
+            return;
+        }
+        self.loan.owed *= 1.0 + INTEREST_PER_TICK;
+        self.loan.next_payment -= 1;
+        if self.loan.next_payment > 0 {
+            return;
+        }
+        self.loan.next_payment = PAYMENT_TICKS;
+        let due = self.loan.installment();
+        if self.resources.get(Resource::Tax) >= due as i64 {
+            self.resources.add(Resource::Tax, -(due as i64));
+            self.loan.owed -= due as f32;
+            self.loan.payments_left -= 1;
+            self.loan.missed = 0;
+            if self.loan.payments_left <= 0 || self.loan.owed < 1.0 {
+                self.loan.clear();
+                self.info("the loan has been paid off");
+            }
+            return;
+        }
+
+        self.loan.missed += 1;
+        self.loan.owed += due as f32 * LATE_FEE;
+        if self.loan.missed < SEIZE_AFTER {
+            self.warn(format!(
+                "missed a loan payment of {}. a late fee was added and the bank will not lend more until it is paid",
+                due
+            ));
+            return;
+        }
+        let seized = self.seize_goods(due);
+        self.loan.owed = (self.loan.owed - seized as f32).max(0.0);
+        self.loan.missed = 0;
+        self.loan.payments_left -= 1;
+        self.warn(format!(
+            "{} loan payments were missed, so the bank took goods worth {} to cover them",
+            SEIZE_AFTER, seized
+        ));
+        if self.loan.payments_left <= 0 || self.loan.owed < 1.0 {
+            self.loan.clear();
+        }
+    }
+
+    /// take goods from the city worth up to `value` at market prices. returns what they were worth
+    fn seize_goods(&mut self, value: i32) -> i32 {
+        let home = match self.home_tile() {
+            Some(h) => h,
+            None => return 0,
+        };
+        let prices = Resource::iter()
+            .filter(|r| r.is_goods())
+            .map(|r| (r, self.market.price(r)))
+            .filter(|x| x.1 > 0.0)
+            .collect::<Vec<_>>();
+        self.with_funds(home, |_, funds| {
+            let mut seized = 0;
+            for (r, price) in prices {
+                let take = (((value - seized) as f32 / price).ceil() as i64).min(funds.get(r));
+                if take <= 0 {
+                    continue;
+                }
+                funds.add(r, -take);
+                seized += (take as f32 * price) as i32;
+                if seized >= value {
+                    break;
+                }
+            }
+            seized
+        })
+        .unwrap_or(0)
+    }
+
+    /// render the bank window, with the loan and its repayment schedule
+    pub fn render_bank(&mut self, egui_ctx: &egui::Context) {
+        if !self.loan.open {
+            return;
+        }
+        let limit = self.credit_limit();
+        let available = self.available_credit();
+        let value = self.city_value();
+        let schedule = self.loan.schedule();
+        let mut borrow = None;
+        let mut repay = None;
+        let mut open = self.loan.open;
+        egui::Window::new(format!("{} Bank", egui_phosphor::BANK))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                ui.small(format!(
+                    "the city is worth {}. the bank lends up to {}, and each {} {} lets it lend more",
+                    value,
+                    limit,
+                    BuildingType::Bank.symbol(),
+                    BuildingType::Bank.name()
+                ));
+                ui.small(format!(
+                    "interest is {:.1}% a tick and a payment is due every {}",
+                    INTEREST_PER_TICK * 100.0,
+                    format_duration((PAYMENT_TICKS as u64) * TICK_SECS)
+                ));
+                ui.separator();
+                ui.horizontal(|ui| {
+                    ui.label("amount");
+                    ui.add(egui::DragValue::new(&mut self.loan.amount).clamp_range(1..=limit.max(1)));
+                });
+                ui.horizontal(|ui| {
+                    ui.label("payments");
+                    for term in [5, 10, 20] {
+                        ui.selectable_value(&mut self.loan.term, term, format!("{}", term));
+                    }
+                });
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(available > 0, egui::Button::new(format!("{} Borrow", egui_phosphor::HAND_COINS)))
+                        .on_hover_text(format!("{} more can be borrowed", available))
+                        .clicked()
+                    {
+                        borrow = Some((self.loan.amount, self.loan.term));
+                    }
+                    if ui.add_enabled(self.loan.owed > 0.0, egui::Button::new("Repay")).clicked() {
+                        repay = Some(self.loan.amount);
+                    }
+                });
+
+                ui.separator();
+                if self.loan.owed <= 0.0 {
+                    ui.label("nothing is owed");
+                    return;
+                }
+                ui.label(format!("owed {}{}", Resource::Tax.symbol(), self.loan.owed.ceil()));
+                if self.loan.missed > 0 {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(200, 60, 60),
+                        format!(
+                            "{} {} missed. after {} the bank takes goods instead",
+                            egui_phosphor::WARNING,
+                            self.loan.missed,
+                            SEIZE_AFTER
+                        ),
+                    );
+                }
+                egui::Grid::new("repayment schedule").striped(true).show(ui, |ui| {
+                    ui.label("due in");
+                    ui.label("payment");
+                    ui.end_row();
+                    for (ticks, payment) in schedule {
+                        ui.label(format_duration(ticks.max(0) as u64 * TICK_SECS));
+                        ui.label(format!("{}{}", Resource::Tax.symbol(), payment));
+                        ui.end_row();
+                    }
+                });
+            });
+        self.loan.open = open;
+        if let Some((amount, term)) = borrow {
+            if let Err(e) = self.borrow(amount, term) {
+                self.info(e);
+            }
+        }
+        if let Some(amount) = repay {
+            self.repay(amount);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn loan(owed: f32, payments_left: i32, next_payment: i32) -> Loan {
+        Loan {
+            owed,
+            payments_left,
+            next_payment,
+            ..Loan::default()
+        }
+    }
+
+    #[test]
+    fn loading_keeps_the_default_amount_and_term() {
+        let bytes = savefile::save_to_mem(0, &loan(1000.0, 4, 5)).unwrap();
+        let loaded: Loan = savefile::load_from_mem(&bytes, 0).unwrap();
+        assert_eq!((loaded.amount, loaded.term), (100, DEFAULT_TERM));
+        assert_eq!(loaded.schedule().len(), 4);
+    }
+
+    #[test]
+    fn schedule_is_empty_without_a_loan() {
+        assert!(Loan::default().schedule().is_empty());
+    }
+
+    #[test]
+    fn schedule_has_a_payment_every_period() {
+        let schedule = loan(1000.0, 4, 5).schedule();
+        let ticks = schedule.iter().map(|x| x.0).collect::<Vec<_>>();
+        assert_eq!(ticks, vec![5, 5 + PAYMENT_TICKS, 5 + PAYMENT_TICKS * 2, 5 + PAYMENT_TICKS * 3]);
+    }
+
+    #[test]
+    fn schedule_pays_back_the_loan_with_interest() {
+        let l = loan(1000.0, 4, 5);
+        let schedule = l.schedule();
+        assert_eq!(schedule[0].1, l.installment());
+        assert_eq!(schedule[0].1, 250);
+        // interest is added between payments, so each one is a little more than the last
+        assert!(schedule.windows(2).all(|x| x[1].1 >= x[0].1));
+        let total = schedule.iter().map(|x| x.1).sum::<i32>();
+        let interest = (1.0 + INTEREST_PER_TICK).powi(PAYMENT_TICKS * 3);
+        assert!(total > 1000 && total as f32 <= 1000.0 * interest + 4.0);
+    }
+
+    #[test]
+    fn schedule_rounds_payments_up() {
+        let schedule = loan(10.0, 3, 0).schedule();
+        assert_eq!(schedule[0].1, 4);
+        assert!(schedule.iter().map(|x| x.1).sum::<i32>() >= 10);
+    }
+}
@@ -0,0 +1,329 @@
+use std::collections::HashMap;
+
+use strum::IntoEnumIterator;
+
+use crate::{
+    building::{BuildingType, Resource},
+    resources::ResourceBag,
+    tile::{Pos, Tile},
+    Data,
+};
+
+/// how many of each good a tile can hold without any warehouses
+const BASE_STOCKPILE: i64 = 100;
+/// how many goods can move between two neighbouring tiles each tick without a road
+const BASE_THROUGHPUT: i64 = 2;
+/// how many more goods can move each tick for every road that crosses the border between two tiles
+const ROAD_THROUGHPUT: i64 = 10;
+
+const FLOW_COLOR: egui::Color32 = egui::Color32::from_rgb(60, 110, 200);
+
+/// goods that moved from one tile to its neighbour on the last tick
+pub struct Flow {
+    pub from: Pos,
+    pub to: Pos,
+    pub goods: ResourceBag,
+}
+
+/// the resources that can be spent on a tile. with logistics turned on goods come from the tile's
+/// own stockpile and everything else comes from the global pool
+pub fn merge_funds(logistics: bool, global: &ResourceBag, stock: &ResourceBag) -> ResourceBag {
+    let mut funds = *global;
+    if logistics {
+        for r in Resource::iter().filter(|r| r.is_goods()) {
+            funds.set(r, stock.get(r));
+        }
+    }
+    funds
+}
+
+/// put resources from `merge_funds` back where they came from once they have been spent or refunded
+pub fn split_funds(logistics: bool, funds: ResourceBag, global: &mut ResourceBag, stock: &mut ResourceBag) {
+    for r in Resource::iter() {
+        match logistics && r.is_goods() {
+            true => stock.set(r, funds.get(r)),
+            false => global.set(r, funds.get(r)),
+        };
+    }
+}
+
+/// how many of each good a tile can hold. warehouses on the tile add their storage to it
+pub fn stockpile_capacity(tile: &Tile) -> i64 {
+    BASE_STOCKPILE + tile.income.made(Resource::Storage)
+}
+
+/// write a list of goods like `🌾5 🪵10 `
+fn list_goods(goods: &ResourceBag) -> String {
+    goods.iter().map(|x| format!("{}{} ", x.0.symbol(), x.1)).collect()
+}
+
+impl Data {
+    /// a copy of the resources that can be spent on a tile
+    pub fn funds(&self, tile: Pos) -> ResourceBag {
+        match self.tiles.get(&tile) {
+            Some(t) => merge_funds(self.logistics, &self.resources, &t.stock),
+            None => merge_funds(self.logistics, &self.resources, &ResourceBag::default()),
+        }
+    }
+
+    /// run something that spends or refunds resources on a tile, taking goods from the tile's
+    /// stockpile when logistics is turned on. returns `None` if the land is not owned
+    pub fn with_funds<T>(
+        &mut self,
+        tile: Pos,
+        f: impl FnOnce(&mut Tile, &mut ResourceBag) -> T,
+    ) -> Option<T> {
+        let t = self.tiles.get_mut(&tile)?;
+        let mut funds = merge_funds(self.logistics, &self.resources, &t.stock);
+        let result = f(t, &mut funds);
+        split_funds(self.logistics, funds, &mut self.resources, &mut t.stock);
+        Some(result)
+    }
+
+    /// how much of each resource the city has, counting the goods in every stockpile
+    pub fn totals(&self) -> ResourceBag {
+        let mut totals = self.resources;
+        if self.logistics {
+            for tile in self.tiles.values() {
+                totals += tile.stock;
+            }
+        }
+        totals
+    }
+
+    /// the tile that goods go to when logistics is turned on, which is the first one bought
+    pub fn home_tile(&self) -> Option<Pos> {
+        match self.tiles.contains_key(&Pos::new(0, 0)) {
+            true => Some(Pos::new(0, 0)),
+            false => self.tiles.keys().min_by_key(|x| (x.y, x.x)).copied(),
+        }
+    }
+
+    /// turn logistics on or off. turning it on moves every good into the home tile's stockpile,
+    /// and turning it off puts the goods from every stockpile back into the global pool
+    pub fn set_logistics(&mut self, on: bool) {
+        if on == self.logistics {
+            return;
+        }
+        self.logistics = on;
+        if on {
+            let home = match self.home_tile() {
+                Some(h) => h,
+                None => return,
+            };
+            for r in Resource::iter().filter(|r| r.is_goods()) {
+                let n = self.resources.get(r);
+                self.resources.set(r, 0);
+                if let Some(t) = self.tiles.get_mut(&home) {
+                    t.stock.add(r, n);
+                }
+            }
+        } else {
+            for tile in self.tiles.values_mut() {
+                self.resources += std::mem::take(&mut tile.stock);
+            }
+            self.flows.clear();
+        }
+        // the actions in the history spent resources from where they used to be
+        self.history.clear();
+    }
+
+    /// the logistics part of an economy tick. goods go into the stockpile of the tile that made them,
+    /// up to what it can hold, and everything else goes into the global pool as usual. unlike the
+    /// shared pool this has to visit every tile, as each one has its own stockpile
+    pub fn produce_goods(&mut self, storage: i64, cash_storage: i64) {
+        for tile in self.tiles.values_mut() {
+            let capacity = stockpile_capacity(tile);
+            self.per_tick += tile.income.production;
+            for (r, n) in tile.income.production.iter() {
+                if r.is_goods() {
+                    // a stockpile over its capacity, such as the home tile just after logistics is
+                    // turned on, keeps what it has but does not get any more
+                    tile.stock.add_up_to(r, n, capacity);
+                } else {
+                    let cap = match r {
+                        Resource::Tax | Resource::CashStorage => storage + cash_storage,
+                        _ => storage,
+                    };
+                    self.resources.add_up_to(r, n, cap);
+                }
+            }
+        }
+    }
+
+    /// how many goods can move between two neighbouring tiles each tick. `b` is the tile to the
+    /// right of or below `a`
+    fn throughput(&self, a: Pos, b: Pos) -> i64 {
+        let roads = (0..8)
+            .filter(|i| {
+                // the cell on the edge of `a` that faces `b`, and the cell across the border from it
+                let (edge, across) = match b.x != a.x {
+                    true => {
+                        let edge = Pos::world_cell(a, Pos::new(*i, 7));
+                        (edge, Pos::new(edge.x + 1, edge.y))
+                    }
+                    false => {
+                        let edge = Pos::world_cell(a, Pos::new(7, *i));
+                        (edge, Pos::new(edge.x, edge.y + 1))
+                    }
+                };
+                self.building_at(edge) == Some(BuildingType::Asphalt)
+                    && self.building_at(across) == Some(BuildingType::Asphalt)
+            })
+            .count() as i64;
+        BASE_THROUGHPUT + ROAD_THROUGHPUT * roads
+    }
+
+    /// move goods between neighbouring tiles. goods spread from the fuller stockpile towards the
+    /// emptier one, limited by the throughput of the border and the room left in the stockpile
+    pub fn transport(&mut self) {
+        let capacity = self
+            .tiles
+            .iter()
+            .map(|(p, t)| (*p, stockpile_capacity(t)))
+            .collect::<HashMap<Pos, i64>>();
+        let mut links = vec![];
+        for pos in self.tiles.keys() {
+            for next in [Pos::new(pos.x + 1, pos.y), Pos::new(pos.x, pos.y + 1)] {
+                if self.tiles.contains_key(&next) {
+                    links.push((*pos, next, self.throughput(*pos, next)));
+                }
+            }
+        }
+        // every link looks at the stockpiles from the start of the tick, so the order they are
+        // handled in does not change where goods go
+        let before = self
+            .tiles
+            .iter()
+            .map(|(p, t)| (*p, t.stock))
+            .collect::<HashMap<Pos, ResourceBag>>();
+
+        let mut flows = vec![];
+        for (a, b, limit) in links {
+            let mut left = limit;
+            let mut there = ResourceBag::default();
+            let mut back = ResourceBag::default();
+            for r in Resource::iter().filter(|r| r.is_goods()) {
+                let have_a = before[&a].get(r);
+                let have_b = before[&b].get(r);
+                let (from, to) = match have_a > have_b {
+                    true => (a, b),
+                    false => (b, a),
+                };
+                let room = capacity[&to] - self.tiles[&to].stock.get(r);
+                let moved = ((have_a - have_b).abs() / 2)
+                    .min(left)
+                    .min(room)
+                    .min(self.tiles[&from].stock.get(r));
+                if moved <= 0 {
+                    continue;
+                }
+                left -= moved;
+                self.tiles.get_mut(&from).unwrap().stock.add(r, -moved);
+                self.tiles.get_mut(&to).unwrap().stock.add(r, moved);
+                match from == a {
+                    true => there.add(r, moved),
+                    false => back.add(r, moved),
+                }
+            }
+            if !there.is_empty() {
+                flows.push(Flow { from: a, to: b, goods: there });
+            }
+            if !back.is_empty() {
+                flows.push(Flow { from: b, to: a, goods: back });
+            }
+        }
+        self.flows = flows;
+    }
+
+    /// draw arrows between tiles for the goods that moved on the last tick
+    pub fn render_flows(&self, egui_ctx: &egui::Context) {
+        if !self.logistics || !self.input_settings.flow_overlay {
+            return;
+        }
+        // above the tiles but below the windows
+        let painter = egui_ctx.layer_painter(egui::LayerId::new(egui::Order::PanelResizeLine, egui::Id::new("flows")));
+        let view = self.view();
+        let centre = |p: Pos| view.tile_rect(p).center();
+        for flow in &self.flows {
+            let from = centre(flow.from);
+            let to = centre(flow.to);
+            let dir = (to - from).normalized();
+            // arrows going opposite ways between the same tiles sit side by side
+            let side = egui::Vec2::new(-dir.y, dir.x) * 12.0 * view.scale;
+            let total = flow.goods.iter().map(|x| x.1).sum::<i64>();
+            let width = 1.0 + (total as f32).ln().max(0.0);
+            painter.arrow(
+                from + dir * 50.0 * view.scale + side,
+                dir * (view.tile_size() - 100.0 * view.scale),
+                egui::Stroke::new(width, FLOW_COLOR),
+            );
+            painter.text(
+                from + (to - from) * 0.5 + side * 2.0,
+                egui::Align2::CENTER_CENTER,
+                list_goods(&flow.goods),
+                egui::FontId::proportional(11.0),
+                FLOW_COLOR,
+            );
+        }
+    }
+
+    /// render the logistics window, with the switch between stockpiles and one shared pool
+    pub fn render_logistics(&mut self, egui_ctx: &egui::Context) {
+        if !self.logistics_open {
+            return;
+        }
+        let mut shared = !self.logistics;
+        let mut open = self.logistics_open;
+        egui::Window::new(format!("{} Logistics", egui_phosphor::TRUCK))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                ui.checkbox(&mut shared, "one shared pool")
+                    .on_hover_text("every resource is kept in one place and can be spent anywhere");
+                if shared {
+                    ui.small("turn this off to keep goods on the tile that made them. they have to be moved over roads to be spent somewhere else");
+                    return;
+                }
+                ui.small(format!(
+                    "goods ({}) are kept on the tile that made them and spread to neighbouring tiles. \
+                     each border moves {} a tick, plus {} for every road that crosses it. \
+                     warehouses make room for more",
+                    Resource::iter()
+                        .filter(|r| r.is_goods())
+                        .map(|r| r.symbol())
+                        .collect::<String>(),
+                    BASE_THROUGHPUT,
+                    ROAD_THROUGHPUT
+                ));
+                ui.checkbox(&mut self.input_settings.flow_overlay, "show flows on the map");
+                ui.separator();
+
+                let mut tiles = self.tiles.keys().copied().collect::<Vec<_>>();
+                tiles.sort_by_key(|x| (x.y, x.x));
+                egui::Grid::new("stockpiles").striped(true).show(ui, |ui| {
+                    ui.label("tile");
+                    ui.label("room");
+                    ui.label("stockpile");
+                    ui.label("in / out");
+                    ui.end_row();
+                    for pos in tiles {
+                        let tile = &self.tiles[&pos];
+                        let moved = |f: &Flow| f.goods.iter().map(|x| x.1).sum::<i64>();
+                        let inflow = self.flows.iter().filter(|f| f.to == pos).map(moved).sum::<i64>();
+                        let outflow = self.flows.iter().filter(|f| f.from == pos).map(moved).sum::<i64>();
+                        ui.label(pos.to_string());
+                        ui.label(format!("{} each", stockpile_capacity(tile)));
+                        ui.label(match list_goods(&tile.stock) {
+                            s if s.is_empty() => "empty".to_owned(),
+                            s => s,
+                        });
+                        ui.label(format!("+{} / -{}", inflow, outflow));
+                        ui.end_row();
+                    }
+                });
+            });
+        self.logistics_open = open;
+        self.set_logistics(!shared);
+    }
+}
@@ -16,7 +16,10 @@ use std::{
 
 use serde_json;
 
-const GLOBAL_VERSION: u32 = 6;
+const GLOBAL_VERSION: u32 = 19;
+
+/// how often the economy ticks, in seconds
+const TICK_SECS: u64 = 3;
 
 use egui::{
     self, Align2, Frame, Id, Pos2, TextStyle, Order, emath,
@@ -31,13 +34,32 @@ extern crate savefile;
 #[macro_use]
 extern crate savefile_derive;
 
+mod blueprint;
 mod building;
 mod tile;
-mod guide;
+mod income;
+mod history;
+mod hud;
+mod inspect;
+mod keys;
+mod land;
+mod loan;
+mod minimap;
+mod logistics;
+mod market;
+mod paint;
+mod picker;
+mod road;
+mod queue;
+mod resources;
+mod saves;
+mod settings;
+mod stats;
+mod tutorial;
 
 use building::*;
 use tile::*;
-use guide::*;
+use resources::{ResourceBag, ResourceMap};
 
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -69,9 +91,18 @@ impl Pos {
         ]
     }
 
-    /// this is the equation that calculates how much a piece of land costs to buy. 
-    fn cost(&self) -> i32 {
-        (self.x.abs().max(self.y.abs()) + 1).pow(3) * 100
+    /// the position of a cell in the world, counting cells from the top left of the tile at (0,0).
+    /// cells are stored in `Tile.land[x][y]` with `x` as the row, so the world `x` comes from the cell's `y`
+    pub fn world_cell(tile: Pos, cell: Pos) -> Pos {
+        Pos::new(tile.x * 8 + cell.y, tile.y * 8 + cell.x)
+    }
+
+    /// split a world cell back into the position of its tile and its cell within the tile
+    pub fn split_world_cell(&self) -> (Pos, Pos) {
+        (
+            Pos::new(self.x.div_euclid(8), self.y.div_euclid(8)),
+            Pos::new(self.y.rem_euclid(8), self.x.rem_euclid(8)),
+        )
     }
 }
 
@@ -79,6 +110,8 @@ impl Pos {
 pub enum SelectTool {
     Add,
     Plan,
+    /// click on a cell to look at the building on it
+    Inspect,
 }
 
 impl SelectTool {
@@ -87,6 +120,16 @@ impl SelectTool {
         (match self {
             SelectTool::Add => egui_phosphor::CURSOR_CLICK,
             SelectTool::Plan => egui_phosphor::CIRCLE_DASHED,
+            SelectTool::Inspect => egui_phosphor::MAGNIFYING_GLASS,
+        })
+        .to_owned()
+    }
+
+    fn name(&self) -> String {
+        (match self {
+            SelectTool::Add => "Build",
+            SelectTool::Plan => "Plan",
+            SelectTool::Inspect => "Inspect",
         })
         .to_owned()
     }
@@ -96,6 +139,10 @@ impl SelectTool {
 pub enum EditTool {
     Build(Building),
     Remove,
+    /// select two corners to copy the buildings between them into a blueprint
+    Copy,
+    /// plan a blueprint where the player clicks
+    Blueprint(blueprint::Blueprint),
 }
 
 impl EditTool {
@@ -104,6 +151,8 @@ impl EditTool {
         (match self {
             EditTool::Build(b) => b.symbol.clone(),
             EditTool::Remove => egui_phosphor::ERASER.to_owned(),
+            EditTool::Copy => egui_phosphor::SELECTION.to_owned(),
+            EditTool::Blueprint(_) => egui_phosphor::STAMP.to_owned(),
         })
         .to_owned()
     }
@@ -112,6 +161,28 @@ impl EditTool {
 pub struct InputSettings {
     pub select_tool: SelectTool,
     pub edit_tool: EditTool,
+    /// buildings to draw faintly over world cells to show what a tool will do
+    pub preview: HashMap<Pos, BuildingType>,
+    /// the two world cell corners of the area being copied
+    pub selection: Option<(Pos, Pos)>,
+    /// the first corner picked with the copy tool
+    pub selection_start: Option<Pos>,
+    /// the drag that is currently painting buildings
+    pub stroke: Option<paint::Stroke>,
+    /// the world cell shown in the inspector
+    pub inspected: Option<Pos>,
+    /// the world cell the pointer was last over, which stays set while the pointer is over a window
+    pub hovered: Option<Pos>,
+    /// colour the roads by whether they lead back to a road hub
+    pub road_overlay: bool,
+    /// draw the goods moving between tiles when logistics is turned on
+    pub flow_overlay: bool,
+    /// the stage whose buildings the number keys pick
+    pub stage: usize,
+    /// the key to show every building symbol is held
+    pub show_symbols: bool,
+    /// the key to copy a whole tile is held
+    pub copy_tile: bool,
 }
 
 impl Default for InputSettings {
@@ -120,6 +191,17 @@ impl Default for InputSettings {
         InputSettings {
             select_tool: SelectTool::Add,
             edit_tool: EditTool::Build(Building::new(&BuildingType::House)),
+            preview: HashMap::new(),
+            selection: None,
+            selection_start: None,
+            stroke: None,
+            inspected: None,
+            hovered: None,
+            road_overlay: false,
+            flow_overlay: true,
+            stage: 0,
+            show_symbols: false,
+            copy_tile: false,
         }
     }
 }
@@ -143,6 +225,10 @@ fn default_toast() -> Toasts {
     .direction(egui::Direction::BottomUp);
     return toasts;
 }
+/// cities from before the tutorial was added have already been played, so they do not start it
+fn finished_tutorial() -> tutorial::Tutorial {
+    tutorial::Tutorial::finished()
+}
 
 
 
@@ -165,10 +251,15 @@ pub struct Data {
     #[savefile_ignore]
     pub screen_offset: (f32, f32),
     pub new_pos: Vec<Pos>,
-    pub resources: HashMap<Resource, i32>,
+    /// saves before version 16 kept resources in a map, which is turned into a bag when they are loaded
+    #[savefile_versions_as = "0..15:ResourceMap"]
+    #[savefile_versions = "16.."]
+    pub resources: ResourceBag,
 
     pub stage: [Stage; 6],
-    ui_scale: f32,
+    /// moved to the global settings file
+    #[savefile_versions = "..7"]
+    ui_scale: savefile::prelude::Removed<f32>,
     game_scale: f32,
 
     #[savefile_introspect_ignore]
@@ -198,9 +289,109 @@ pub struct Data {
     #[savefile_ignore]
     toasts: Toasts,
 
+
+    /// total time spent in this city, in seconds
+    #[savefile_versions = "7.."]
+    play_time: u64,
+
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    history: history::History,
+
+    /// how much of each resource was produced on the last tick
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    per_tick: ResourceBag,
+
+    /// the world cells of planned buildings, in the order they will be built
+    #[savefile_versions = "9.."]
+    build_queue: Vec<Pos>,
+
+    #[savefile_ignore]
+    queue_open: bool,
+
+    /// keep goods in a stockpile on each tile instead of one shared pool
+    #[savefile_versions = "11.."]
+    logistics: bool,
+
+    #[savefile_ignore]
+    logistics_open: bool,
+
+    /// what the working buildings in the whole city make each tick, kept up to date as buildings change
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    income: income::Income,
+
+    /// false when `income` needs to be worked out from scratch, such as after loading
+    #[savefile_ignore]
+    income_current: bool,
+
+    /// what the price of land depended on when the value of every tile was last worked out
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    land_basis: Option<(usize, usize, land::LandPricing)>,
+
+    /// the minimap as it was last drawn, or none if the land or buildings have changed since
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    minimap: Option<minimap::Minimap>,
+
+    /// the tiles whose buildings have changed since the road network was last worked out
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    roads_dirty: HashSet<Pos>,
+
+    /// the world cells with asphalt on them
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    road_cells: HashSet<Pos>,
+
+    /// the world cells with a road hub on them
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    road_hubs: HashSet<Pos>,
+
+    /// the world cells with asphalt that leads back to a road hub
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    connected_roads: HashSet<Pos>,
+
+    /// the goods that moved between tiles on the last tick
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    flows: Vec<logistics::Flow>,
+
+    /// the prices of goods and how they have changed
+    #[savefile_versions = "12.."]
+    market: market::Market,
+
+    /// the tax borrowed from the bank
+    #[savefile_versions = "13.."]
+    loan: loan::Loan,
+
+    /// how the price of land is worked out
+    #[savefile_versions = "14.."]
+    land_pricing: land::LandPricing,
+
     #[savefile_ignore]
-    guide: bool,
+    land_open: bool,
 
+    /// the economy over the last hour of play
+    #[savefile_versions = "15.."]
+    stats: stats::Stats,
+
+    /// which resources are shown in the top left corner and how
+    #[savefile_versions = "17.."]
+    hud: hud::Hud,
+
+    /// the favourite and recently used buildings in the building menu
+    #[savefile_versions = "18.."]
+    picker: picker::Picker,
+
+    /// how far the player is through the tutorial
+    #[savefile_versions = "19.."]
+    #[savefile_default_fn = "finished_tutorial"]
+    tutorial: tutorial::Tutorial,
 }
 
 impl Data {
@@ -256,7 +447,30 @@ impl Data {
     fn new(name: String) -> Data {
         let mut d = Data {
             name,
-            guide: false,
+            play_time: 0,
+            history: history::History::default(),
+            per_tick: ResourceBag::default(),
+            build_queue: vec![],
+            queue_open: false,
+            logistics: false,
+            logistics_open: false,
+            flows: vec![],
+            income: income::Income::default(),
+            income_current: false,
+            land_basis: None,
+            minimap: None,
+            roads_dirty: HashSet::new(),
+            road_cells: HashSet::new(),
+            road_hubs: HashSet::new(),
+            connected_roads: HashSet::new(),
+            market: market::Market::default(),
+            loan: loan::Loan::default(),
+            land_pricing: land::LandPricing::default(),
+            land_open: false,
+            stats: stats::Stats::default(),
+            hud: hud::Hud::default(),
+            picker: picker::Picker::default(),
+            tutorial: tutorial::Tutorial::default(),
             quick_menu: false,
             switch_tool_rect: egui::Rect::from_min_size(
                 egui::Pos2::new(0.0, 0.0),
@@ -266,18 +480,14 @@ impl Data {
                 egui::Pos2::new(0.0, 0.0),
                 egui::Vec2::new(0.0, 0.0),
             ),
-            ui_scale: 1.3,
+            ui_scale: savefile::prelude::Removed::new(),
             game_scale: 1.0,
             new_pos: vec![Pos::new(0, 0)],
             tiles: HashMap::new(),
-            input_settings: InputSettings {
-                select_tool: SelectTool::Add,
-                // the default thing to build is a house
-                edit_tool: EditTool::Build(Building::new(&BuildingType::House)),
-                
-            },
+            // the default thing to build is a house
+            input_settings: InputSettings::default(),
             screen_offset: (100.0, 100.0),
-            resources: HashMap::new(),
+            resources: ResourceBag::default(),
             stage: [// all stages start out locked
                 Stage::new(1),
                 Stage::new(2),
@@ -290,15 +500,11 @@ impl Data {
             popup_hover: false,
             toasts: default_toast(),
         };
-        for r in Resource::iter() {
-            d.resources.insert(r, 0);
-        }
-
         // give the player just enough resources to build a house and some farmland. 
-        d.resources.insert(Resource::Seed, 10);
-        d.resources.insert(Resource::Food, 10);
-        d.resources.insert(Resource::Wood, 10);
-        d.resources.insert(Resource::Storage, 100);
+        d.resources.set(Resource::Seed, 10);
+        d.resources.set(Resource::Food, 10);
+        d.resources.set(Resource::Wood, 10);
+        d.resources.set(Resource::Storage, 100);
         d.tiles
             .insert(Pos { x: 0, y: 0 }, Tile::new(Pos { x: 0, y: 0 }));
         d.update_new_pos();
@@ -317,7 +523,59 @@ impl Data {
             });
         }
         self.new_pos = new_tiles.difference(&tiles).cloned().collect();
+        self.minimap = None;
+    }
+
+    /// the most resources that can be stored, and the extra that can be stored as cash
+    fn storage_caps(&self) -> (i64, i64) {
+        (
+            100 + self.income.made(Resource::Storage),
+            self.income.made(Resource::CashStorage),
+        )
+    }
+
+    /// advance the economy by one tick. buildings produce resources, stages are unlocked and planned buildings are built
+    fn tick(&mut self) {
+        self.update_roads();
+        let (storage, cash_storage) = self.storage_caps();
+        self.per_tick = ResourceBag::default();
+        let before = self.totals();
+        if self.logistics {
+            self.produce_goods(storage, cash_storage);
+            self.transport();
+        } else {
+            // the income of the city is kept up to date as buildings change, so this does not depend on the number of tiles
+            self.per_tick = self.income.production;
+            for (r, n) in self.income.production.iter() {
+                let cap = match r {
+                    Resource::Tax | Resource::CashStorage => storage + cash_storage,
+                    _ => storage,
+                };
+                self.resources.set(r, self.resources.get(r).saturating_add(n).min(cap));
+            }
+        }
+
+        self.market_tick(&before);
+        self.loan_tick();
+
+        let totals = self.totals();
+        for i in &mut self.stage {
+            if !i.enabled {
+                if i.unlock_at
+                    .iter()
+                    .all(|x| totals.get(x.0) >= x.1 as i64)
+                {
+                    i.enabled = true;
+                    // unlocking a stage cannot be undone, so neither can anything before it
+                    self.history.clear();
+                }
+            }
+        }
+        self.history.on_tick();
+        self.process_queue();
+        self.record_stats();
     }
+
     /// add a building to the land tile
     fn add_buildings(&mut self, pos: [Pos; 5]) {
         let mut new_hash = HashMap::new();
@@ -333,88 +591,184 @@ impl Data {
         if let Some(b) = self.tiles.get_mut(&pos[4]) {
             b.neighbors_buildings = new_hash;
         }
+        self.refresh_income(pos[4]);
+        self.roads_dirty.insert(pos[4]);
+        self.sync_tile_queue(pos[4]);
+        self.minimap = None;
+    }
+    /// where the land is drawn on the screen
+    fn view(&self) -> View {
+        View {
+            offset: self.screen_offset,
+            scale: self.game_scale.clamp(MIN_ZOOM, MAX_ZOOM),
+        }
+    }
+
+    /// the tiles that are at least partly inside `screen`. the tiles are kept in a map by their grid
+    /// position, so the map works as a spatial index: the grid positions under the screen are looked up
+    /// directly, unless there are more of them than tiles, in which case each tile is tested instead
+    fn visible_tiles(&self, screen: egui::Rect) -> Vec<Pos> {
+        let view = self.view();
+        let min = view.to_world(screen.min).floor();
+        let max = view.to_world(screen.max).floor();
+        let area = (max.x - min.x + 1.0) * (max.y - min.y + 1.0);
+        if area > self.tiles.len() as f32 {
+            return self
+                .tiles
+                .keys()
+                .filter(|p| view.tile_rect(**p).intersects(screen))
+                .copied()
+                .collect();
+        }
+        let mut visible = vec![];
+        for x in min.x as i32..=max.x as i32 {
+            for y in min.y as i32..=max.y as i32 {
+                let pos = Pos::new(x, y);
+                if self.tiles.contains_key(&pos) {
+                    visible.push(pos);
+                }
+            }
+        }
+        visible
     }
+
+    /// draw the tiles that are zoomed out too far to build on with macroquad, all in one pass before
+    /// egui is drawn on top. this keeps large cities fast, as each tile drawn with egui is an area
+    /// with a widget for every cell
+    fn draw_distant(&self, pixels_per_point: f32, palette: Palette) {
+        let view = self.view();
+        let screen = egui::Rect::from_min_size(
+            Pos2::ZERO,
+            egui::Vec2::new(screen_width(), screen_height()) / pixels_per_point,
+        );
+        for pos in self.visible_tiles(screen) {
+            let tile = &self.tiles[&pos];
+            if view.summary() || tile.summary {
+                tile.draw_summary(view, pixels_per_point, palette);
+            }
+        }
+    }
+
+    /// zoom the land in or out by a factor, keeping the point under `around` in the same place on the screen
+    fn zoom(&mut self, factor: f32, around: Pos2) {
+        let at = self.view().to_world(around);
+        self.game_scale = (self.view().scale * factor).clamp(MIN_ZOOM, MAX_ZOOM);
+        let moved = self.view().to_screen(at);
+        self.screen_offset = (
+            self.screen_offset.0 + around.x - moved.x,
+            self.screen_offset.1 + around.y - moved.y,
+        );
+    }
+
     /// renders the current savefile using egui
     fn render(&mut self, egui_ctx: &egui::Context) {
         let mut update_adjacent: Option<Pos> = None;
-
+        let mut hovered: Option<Pos> = None;
+        let mut clicked: Option<Pos> = None;
+        let mut copy_tile: Option<Pos> = None;
+        let mut failed: Option<tile::Invalid> = None;
+        self.update_roads();
+        let view = self.view();
+
+        // zoomed out tiles are drawn by `draw_distant`, so here they only need a tooltip
+        if let Some(pointer) = egui_ctx.pointer_hover_pos().filter(|_| !egui_ctx.is_pointer_over_area()) {
+            let at = view.to_world(pointer).floor();
+            if let Some(tile) = self.tiles.get(&Pos::new(at.x as i32, at.y as i32)) {
+                if view.summary() || tile.summary {
+                    egui::show_tooltip_at_pointer(egui_ctx, Id::new("distant tile"), |ui| {
+                        ui.label(tile.summary_text());
+                    });
+                }
+            }
+        }
     
-        for i in &mut self.tiles {
-            // check if land tile should be rendered by 
-            if egui_ctx.screen_rect().contains(egui::Pos2::new(
-                self.screen_offset.0 + (i.0.x as f32) * 202.0,
-                self.screen_offset.1 + (i.0.y as f32) * 202.0,
-            )) || egui_ctx.screen_rect().contains(egui::Pos2::new(
-                self.screen_offset.0 + (i.0.x as f32) * 202.0 + 202.0,
-                self.screen_offset.1 + (i.0.y as f32) * 202.0 + 202.0,
-            )) || egui_ctx.screen_rect().contains(egui::Pos2::new(
-                self.screen_offset.0 + (i.0.x as f32) * 202.0,
-                self.screen_offset.1 + (i.0.y as f32) * 202.0 + 202.0,
-            )) || egui_ctx.screen_rect().contains(egui::Pos2::new(
-                self.screen_offset.0 + (i.0.x as f32) * 202.0 + 202.0,
-                self.screen_offset.1 + (i.0.y as f32) * 202.0,
-            )) {
-                if i.1.render(
+        for pos in self.visible_tiles(egui_ctx.screen_rect()) {
+            let tile = self.tiles.get_mut(&pos).unwrap();
+            if !view.summary() && !tile.summary {
+                // with logistics turned on a tile can only spend the goods in its own stockpile
+                let mut funds = logistics::merge_funds(self.logistics, &self.resources, &tile.stock);
+                let response = tile.render(
                     egui_ctx,
                     &self.input_settings,
-                    self.screen_offset,
-                    &mut self.resources,
+                    view,
+                    &mut funds,
                     self.popup_hover,
-                ) {
-                    update_adjacent = Some(*i.0);
+                    &mut self.history,
+                );
+                logistics::split_funds(self.logistics, funds, &mut self.resources, &mut tile.stock);
+                if response.changed {
+                    update_adjacent = Some(pos);
+                }
+                hovered = hovered.or(response.hovered);
+                clicked = clicked.or(response.clicked);
+                if response.copy_tile {
+                    copy_tile = Some(pos);
                 }
+                failed = failed.or(response.failed);
             }
         }
-        let mut add: Vec<Pos> = vec![];
-        for i in &self.new_pos {
-            if &i.cost() <= self.resources.get(&Resource::Tax).unwrap_or(&0)
-                
-            {
-                egui::Window::new(i.to_string())
-                    .title_bar(false)
-                    .resizable(false)
-                    .collapsible(false)
-                    .frame(Frame::none())
-                    .fixed_rect(egui::Rect::from_two_pos(
-                        Pos2::new(
-                            self.screen_offset.0
-                                + (i.x as f32) * 202.0
-                                + (match i.x < 0 {
-                                    true => 100.0,
-                                    false => 0.0,
-                                }),
-                                
-                            self.screen_offset.1
-                                + (i.y as f32) * 202.0
-                                + (match i.y < 0 {
-                                    true => 180.0,
-                                    false => 0.0,
-                                }),
-                        ),
-                        Pos2::new(
-                            self.screen_offset.0 + ((i.x + 1) as f32) * 202.0,
-                            self.screen_offset.1 + ((i.y + 1) as f32) * 240.0,
-                        ),
-                    ))
-                    .show(egui_ctx, |ui| {
-                        ui.add(egui::widgets::Button::new(format!("${}", i.cost())))
-                            .clicked()
-                            .then(|| {
-                                *self.resources.get_mut(&Resource::Tax).unwrap_or(&mut 0) -=
-                                    i.cost();
-                                add.push(*i);
-                            });
-                    });
+        self.render_land_prices(egui_ctx);
+
+        if let Some(pos) = update_adjacent {
+                self.add_buildings(pos.get_adjacent());
+        }
+        if let Some(reason) = failed {
+            self.info(reason);
+        }
+
+        if let Some(pos) = copy_tile {
+            let corner = Pos::world_cell(pos, Pos::new(0, 0));
+            self.input_settings.edit_tool = EditTool::Blueprint(blueprint::Blueprint::from_world(
+                self,
+                corner,
+                corner.added(Pos::new(7, 7)),
+            ));
+        }
+        if hovered.is_some() {
+            self.input_settings.hovered = hovered;
+        }
+        self.use_cell_tools(egui_ctx, hovered, clicked);
+        self.update_stroke(egui_ctx, hovered);
+    }
+
+    /// handle the tools that work on world cells rather than on a single tile, and work out what they should preview
+    fn use_cell_tools(&mut self, egui_ctx: &egui::Context, hovered: Option<Pos>, clicked: Option<Pos>) {
+        if self.input_settings.select_tool == SelectTool::Inspect {
+            if let Some(cell) = clicked {
+                self.input_settings.inspected = Some(cell);
             }
+            self.input_settings.selection_start = None;
+            self.input_settings.selection = None;
+            self.input_settings.preview.clear();
+            return;
         }
-        for i in add {
-            self.tiles.insert(i, Tile::new(i));
-            self.update_new_pos();
+        match (&self.input_settings.edit_tool, clicked) {
+            (EditTool::Copy, Some(cell)) => match self.input_settings.selection_start.take() {
+                None => self.input_settings.selection_start = Some(cell),
+                Some(start) => {
+                    let blueprint = blueprint::Blueprint::from_world(self, start, cell);
+                    egui_ctx.output_mut(|o| o.copied_text = blueprint.to_text());
+                    self.input_settings.edit_tool = EditTool::Blueprint(blueprint);
+                }
+            },
+            (EditTool::Blueprint(b), Some(cell)) => {
+                let b = b.clone();
+                self.paste_blueprint(&b, cell);
+            }
+            _ => {}
         }
 
-        if let Some(pos) = update_adjacent {
-                self.add_buildings(pos.get_adjacent());
+        if !matches!(self.input_settings.edit_tool, EditTool::Copy) {
+            self.input_settings.selection_start = None;
         }
+        self.input_settings.selection = self
+            .input_settings
+            .selection_start
+            .map(|start| (start, hovered.unwrap_or(start)));
+        self.input_settings.preview = match (&self.input_settings.edit_tool, hovered) {
+            (EditTool::Blueprint(b), Some(cell)) => b.cells(cell).collect(),
+            _ => HashMap::new(),
+        };
     }
 }
 
@@ -426,24 +780,18 @@ async fn main() {
     
 
 
-    let mut per_sec:HashMap<Resource, i32> = HashMap::new();
     let mut data = Data::new("".to_owned());
     let mut menu = true;
 
 
     
 
-    match savefile::load_file::<Data, &str>("game_instance.bin", GLOBAL_VERSION) {
-        Ok(d) => {
-            // create new folder called game files and then move the file "game_instance.bin" into it
-            println!("{:?}",std::fs::create_dir("saves"));
-            savefile::save_file("saves/game_instanceOld World.bin", GLOBAL_VERSION, &d).unwrap();
-        }
-        Err(e) => {
-            // display an error message if a file could not be loaded
-            // data.error(e);
-        }
-    };
+    let mut settings = settings::Settings::load();
+
+    // move saves from older versions of the game into the data directory
+    for e in saves::migrate_legacy() {
+        data.error(e);
+    }
     let mut og_ppp = 0.0;
     println!("Test 3");
 
@@ -452,8 +800,8 @@ async fn main() {
         let mut fonts = egui::FontDefinitions::default();
         egui_phosphor::add_to_fonts(&mut fonts);
         egui_ctx.set_fonts(fonts);
-        // set to light mode
-        egui_ctx.set_visuals(egui::Visuals::light());
+        // set the theme and scale from the settings
+        settings.apply(egui_ctx);
         let mut style = (*egui_ctx.style()).clone();
         // set the default font to monospace
         style.override_text_style = Some(TextStyle::Monospace);
@@ -463,59 +811,32 @@ async fn main() {
     let mut offset_start = (0.0, 0.0);
     let mut start_in_area = false;
 
+    let mut save_browser = saves::SaveBrowser::default();
+    let mut blueprints = blueprint::BlueprintLibrary::load();
+
     let mut process = Instant::now();
+    let mut last_save = Instant::now();
+    // closing the window is handled below, so that the city can be saved first
+    prevent_quit();
     loop {
+        if is_quit_requested() {
+            if !menu {
+                if let Err(e) = saves::save(&data) {
+                    eprintln!("could not save the city before quitting: {:?}", e);
+                }
+            }
+            break;
+        }
 
-        clear_background(WHITE);
+        clear_background(tile::to_macroquad(Palette::of(settings.dark_mode).background));
         let mut hover_text: Option<String> = None;
         if menu {// render the menu if the menu is open
             egui_macroquad::ui(|egui_ctx| {
-                // set the ui scale to the ui scale in the data struct    
-                egui_ctx.set_pixels_per_point(data.ui_scale);
+                // set the ui scale to the ui scale in the settings
+                egui_ctx.set_pixels_per_point(settings.ui_scale);
                 egui::SidePanel::right("Right side").show(egui_ctx, |ui| {
-                    // check if the "saves/" directory exists. then try to get all teh file within the directory. if it fails then display an error message
-                    if let Ok(files) = std::fs::read_dir("saves/") {
-                        
-                        ui.heading("Saved Games");
-                        ui.separator();
-                        egui::ScrollArea::vertical().show(ui, |ui| {
-                        for f in files {// display a list of all the files. 
-                            let f = match f {Ok(f) => f, _ => {continue;}};
-                            if f.file_type().unwrap().is_file() {
-                                // display file name and a button to load the file
-                                ui.add(egui::widgets::Button::new(f.file_name().to_str().unwrap().replace(".bin","").replace("game_instance_"," ")))
-                                    .clicked()
-                                    .then(|| {
-                                        match  savefile::load_file::<Data, &str>(
-                                            &format!("saves/{}", f.file_name().to_str().unwrap()),GLOBAL_VERSION) {
-                                            Ok(load) => {
-                                                data = load;
-                                                let mut reset = false;
-                                                for (i,s) in data.stage.iter().enumerate() {
-                                                    if s.buildings.len() != Stage::new(i as i32).buildings.len() {
-                                                        reset = true;
-                                                        break;
-                                                    }
-                                                }
-                                                if reset {
-                                                    for i in 0..data.stage.len() {
-                                                        data.stage[i] = Stage::new(i as i32 +1);
-                                                    }
-                                                    
-                                                }
-                                                menu = false;
-                                            }
-                                            Err(e) => {
-                                                data.error(e)
-                                            },
-                                        }
-                                        
-                                    });
-                                }
-                            }
-                        });
-                    }else {
-                        ui.label("Error loading files");
+                    if save_browser.render(ui, &mut data) {
+                        menu = false;
                     }
                 });
 
@@ -530,9 +851,22 @@ async fn main() {
                             .clip_text(false)
                             .desired_width(140.0)
                         );
+                        if data.name != "" && saves::save_path(&data.name).exists() {
+                            ui.small("a city with this name already exists and will be overwritten");
+                        }
                         if data.name != "".to_owned() && ui.add(egui::widgets::Button::new(format!("Create New City:\"{}\"", data.name)))
                             .clicked(){menu = false;}
+                        ui.separator();
+                        if ui.small_button(format!("{} Settings", egui_phosphor::GEAR)).clicked() {
+                            settings.open = !settings.open;
+                        }
             });
+                if settings.render(egui_ctx) {
+                    settings.apply(egui_ctx);
+                    if let Err(e) = settings.save() {
+                        data.error(e);
+                    }
+                }
                 data.toasts.show(egui_ctx);
 
 
@@ -540,6 +874,10 @@ async fn main() {
             });
             egui_macroquad::draw();
 
+            // time spent on the menu is not play time, so the timers start again when a city is opened
+            process = Instant::now();
+            last_save = Instant::now();
+
             next_frame().await;
             // continue to prevent the game from rendering before the user has exited the menu
             continue;
@@ -551,213 +889,55 @@ async fn main() {
             data.popup_hover = false;
         }
         // save teh game every three seconds and also precess the resources that the 
-        if process.elapsed().as_secs() >= 3 {
-            let filename = format!("saves/game_instance_{}.bin", data.name);
+        if process.elapsed().as_secs() >= TICK_SECS {
+            data.play_time += TICK_SECS;
             process = Instant::now();
-            let mut storage = 100;
-            let mut cash_storage = 0;
-            for i in &mut data.tiles {// iterate through all the tiles and process the storage and cash storage
-                (storage, cash_storage) = i.1.process_storage(storage, cash_storage);
-            }
-            per_sec.clear();
-            for i in &mut data.tiles {
-                i.1.processes_resources(&mut data.resources, storage, cash_storage, &mut per_sec) 
-                
-            }
-
-            for i in &mut data.stage {
-                if !i.enabled {
-                    if i.unlock_at
-                        .iter()
-                        .all(|x| data.resources.get(&x.0).unwrap_or(&0) >= &x.1)
-                    {
-                        i.enabled = true;
-                    }
-                }
-            }
-            match savefile::save_file(&filename, GLOBAL_VERSION, &data) {
+            data.tick();
+        }
+        if last_save.elapsed().as_secs() >= settings.autosave_secs {
+            last_save = Instant::now();
+            match saves::save(&data) {
                 Ok(_) => {}
                 Err(e) => data.error(e),
             }
         }
         
         egui_macroquad::ui(|egui_ctx| {
-            let mut unlock = -1;
+            let mut settings_changed = false;
             
-            egui_ctx.set_pixels_per_point(data.ui_scale);
+            egui_ctx.set_pixels_per_point(settings.ui_scale);
             data.render(egui_ctx);
 
-            let window_id = Id::new("popup selector");
-            let mut popup_layer_id = egui::LayerId::new(egui::Order::Foreground, window_id);
-                match egui::Window::new(format!("Build: {}", &data.input_settings.edit_tool.icon()))
-                    .collapsible(true)
-                    .id(window_id)
-                    .open(&mut data.popup)
-                    .scroll2([false, true])
-                    .show(egui_ctx, |ui| {
-
-ui.vertical(|ui| {
-        ui.checkbox(&mut data.quick_menu, "Quick Menu");
-        if data.quick_menu {
-            for s in data.stage.iter_mut() {
-                ui.separator();
-                if s.enabled { ui.horizontal(|ui| {
-                        for b in &s.buildings {
-                            let building = Building::new(b);
-                            if ui.small_button(format!("{}", b.symbol())).on_hover_text(format!("{}",building.cost.iter().map(|x| format!("{}{} ",x.0.symbol(), x.1)).collect::<String>())).clicked() {
-                                data.input_settings.edit_tool = EditTool::Build(Building::new(b));
-                            }
-                        }
-                    });
-                } else {
-                    // show a lock symbol and a button to unlock it if the user hasn't reached the checkpoint to unlock it
-                    ui.small_button(format!("{} unlock early", egui_phosphor::LOCK)).clicked().then(|| {s.enabled = true;});
+            data.render_picker(egui_ctx);
+
+            // keys that are held only count for the frames they are held in
+            data.input_settings.show_symbols = false;
+            data.input_settings.copy_tile = false;
+            for action in settings.keys.actions(egui_ctx) {
+                match action {
+                    keys::Action::Blueprints => blueprints.open = !blueprints.open,
+                    keys::Action::Settings => settings.open = !settings.open,
+                    _ => data.run_action(action, egui_ctx),
                 }
             }
-        }else{
-            if ui.small_button("Delete").clicked() {
-                data.input_settings.edit_tool = EditTool::Remove;
-            }
-        popup_layer_id = ui.layer_id();
-        // iter over them stages and display them
-
-        // display the different buildings
-        for i in &data.stage {
-            if i.enabled {
-                ui.heading(format!(
-                    "{} Stage {}",
-                    egui_phosphor::LOCK_OPEN,
-                    i.num
-                ));
-                ui.group(|ui| {
-                    for j in &i.buildings {
-                        let building = Building::new(j);
-                        if ui
-                            .small_button(format!("{} {}",j.symbol(),j.name()))
-                            .clicked(){
-                            data.input_settings.edit_tool =
-                                EditTool::Build(Building::new(j));
-                        }
-                        egui::Grid::new(j.name()+&i.title+&j.symbol()).show(
-                            ui,
-                            |ui| {
-                                ui.label(
-                                    "Costs ".to_owned()
-                                        + &building
-                                            .cost
-                                            .iter().map(|x| {format!("{} {},",x.0.symbol(),x.1)}).collect::<String>());
-                                
-                                
-                                ui.end_row();
-                                if j.output().len() > 0 {
-                                    ui.label(
-                                        "Outputs ".to_owned()
-                                            + &j.output().iter().map(|x| {format!("{} {},",x.0.symbol(),x.1)})
-                                                .collect::<String>(),
-                                    );
-                                    ui.end_row();
-                                }
-                                if building.required_adj.len() > 0 {
-                                    ui.label(
-                                        "must be placed next to: ".to_owned()
-                                            + &building
-                                                .required_adj
-                                                .iter()
-                                                .map(|x| {
-                                                    x.symbol().replace(
-                                                        "  ",
-                                                        "[empty space]",
-                                                    )
-                                                })
-                                                .collect::<String>(),
-                                    );
-                                    ui.end_row();
-                                }
-
-                                ui.label(
-                                    "can be next to: ".to_owned()
-                                        + &building
-                                            .optional_adj
-                                            .iter()
-                                            .map(|x| {
-                                                x.symbol().replace(
-                                                    "  ",
-                                                    "[empty space]",
-                                                )
-                                            })
-                                            .collect::<String>(),
-                                );
-                                ui.end_row();
-                                if building.tile_adj.len() > 0 {
-                                    ui.label(
-                                        "must be on the land grid as: ".to_owned()
-                                            + &building
-                                                .tile_adj
-                                                .iter()
-                                                .map(|x| {
-                                                    x.symbol().replace(
-                                                        "  ",
-                                                        "[empty space]",
-                                                    )
-                                                })
-                                                .collect::<String>(),
-                                    );
-                                    ui.end_row();
-                                }
-                            },
-                        );
-                        ui.add_sized(
-                            [ui.available_width(), 0.0],
-                            egui::Label::new(""),
-                        );
-                    }
-                });
-            } else {
-                ui.heading(format!(
-                    "{} Stage {}",
-                    egui_phosphor::LOCK,
-                    i.num
-                ));
-                ui.group(|ui| {
-                    ui.label("Unlock at");
-                    for resource in &i.unlock_at {
-                        egui::Grid::new(resource.0.name()+&i.title).show(ui, |ui| {
-                            ui.label(format!(
-                                "{} {} {}",
-                                resource.0.symbol(),
-                                resource.0.name(),
-                                resource.1
-                            ));
-                        });
-                    }
-                    if ui.small_button("Unlock Early").clicked() {
-                        unlock = i.num - 1;
-                    }
-                    ui.add_sized(
-                        [ui.available_width(), 0.0],
-                        egui::Label::new(""),
-                    );
-                });
-                break;
-            }
-        }
-    }
-    });
-                    }) {
-
-                    Some(a) => {
-                        if let Some(p) = egui_ctx.pointer_hover_pos() { 
-                        data.popup_hover = a.response.rect.signed_distance_to_pos(p) < 0.0;
-                    }
-                    },
-                    None => {},
-};
-            
 
-            // egui_ctx.move_to_top(popup_layer_id);
-
-            if unlock != -1 && data.stage.len() > (unlock as usize) {
-                data.stage[unlock as usize].enabled = true;
+            // zoom the land with the mouse wheel or a pinch over the land
+            let (zoom, around) = egui_ctx.input(|o| {
+                let over_land = o
+                    .pointer
+                    .hover_pos()
+                    .map(|p| egui_ctx.layer_id_at(p).map(|l| l.order == Order::Background).unwrap_or(true))
+                    .unwrap_or(false);
+                let mut zoom = 1.0;
+                if over_land {
+                    zoom *= o.zoom_delta() * (o.scroll_delta.y * 0.002).exp();
+                }
+                (zoom, o.pointer.hover_pos())
+            });
+            if zoom != 1.0 {
+                if let Some(p) = around {
+                    data.zoom(zoom, p);
+                }
             }
 
             egui_ctx.input(|o| {
@@ -769,7 +949,8 @@ ui.vertical(|ui| {
                         
                         data.input_settings.select_tool = match &data.input_settings.select_tool {
                             SelectTool::Add => SelectTool::Plan,
-                            SelectTool::Plan => SelectTool::Add,
+                            SelectTool::Plan => SelectTool::Inspect,
+                            SelectTool::Inspect => SelectTool::Add,
                         };
                     }
                     if data
@@ -797,50 +978,8 @@ ui.vertical(|ui| {
                     }
                 }
             });
-            egui::Area::new("data")
-            .fixed_pos(egui::Pos2::new(5.0, 5.0))
-            
-            .order(Order::Foreground)
-            .show(egui_ctx, |ui| {
-                ui.group(|ui| {
-                    egui::Frame::default()
-                    .fill(egui::Color32::from_rgb(255, 255, 255))
-                    .show(ui, |ui| {
-                egui::Grid::new("grid")
-                                        .show(ui, |ui| {
-                    let show_full_data = ui.rect_contains_pointer(
-                        egui::Rect::from_min_size(
-                            egui::Pos2::new(0.0, 0.0),
-                            egui::Vec2::new(100.0, 500.0),
-                        ),
-                    );
-                    
-                    let max_storage = data.resources.get(&Resource::Storage).unwrap_or(&0);
-                    let max_cash = data.resources.get(&Resource::CashStorage).unwrap_or(&0);
-                    for i in data.resources.iter() {
-                        if i.1 == &0 {
-                            continue;
-                        }
-                            if show_full_data {
-                                ui.label(format!("{} {}", i.0.symbol(), i.0.name()));
-                                ui.label(format!("{}", i.1));
-                                ui.label(format!("{}/s", (*per_sec.get(i.0).unwrap_or(&0) as f32/0.3).round()/10.0));
-                                ui.label(format!("{} %",( (*i.1 as f32)/(*max_storage as f32 + match i.0 {
-                                    Resource::Tax => *max_cash as f32,
-                                    _ => 0.0,
-                                })*100.0).round()));
-
-                                ui.end_row();
-                            } else {
-                                ui.label(format!("{} {}",i.0.symbol(), i.1));
-                                ui.end_row();
-                            }
-
-                        }
-                    });
-                });
-                });
-            });
+            data.render_hud(egui_ctx);
+            data.render_hotbar(egui_ctx, &settings.keys);
 
             egui::Area::new("buttons")
             .anchor(Align2::LEFT_BOTTOM, [30.0,-30.0])
@@ -848,17 +987,41 @@ ui.vertical(|ui| {
             
             .show(egui_ctx, |ui| {
                 ui.vertical(|ui| {
-                    ui.small_button(egui_phosphor::ERASER.to_owned()+" Destroy").clicked().then(|| {
+                    ui.small_button(egui_phosphor::ERASER.to_owned()+" Destroy").on_hover_text(settings.keys.hint(egui_ctx, "Destroy", keys::Action::RemoveTool)).clicked().then(|| {
                         data.input_settings.edit_tool = EditTool::Remove;
                     });
-                ui.checkbox(&mut data.popup, format!("{} building menu", &data.input_settings.edit_tool.icon()));
-                let mut planning_mode = data.input_settings.select_tool == SelectTool::Plan;
-                if ui.checkbox(&mut planning_mode, format!("{} Planning Mode", &data.input_settings.select_tool.icon())).clicked() {
-                    data.input_settings.select_tool = match planning_mode {
-                        true => SelectTool::Plan,
-                        false => SelectTool::Add,
-                    };
-                };
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(data.history.can_undo(), egui::Button::new(egui_phosphor::ARROW_COUNTER_CLOCKWISE).small())
+                            .on_hover_text(settings.keys.hint(egui_ctx, "Undo", keys::Action::Undo)).clicked() {
+                            data.undo();
+                        }
+                        if ui.add_enabled(data.history.can_redo(), egui::Button::new(egui_phosphor::ARROW_CLOCKWISE).small())
+                            .on_hover_text(settings.keys.hint(egui_ctx, "Redo", keys::Action::Redo)).clicked() {
+                            data.redo();
+                        }
+                    });
+                let build_menu = ui.checkbox(&mut data.popup, format!("{} building menu", &data.input_settings.edit_tool.icon()));
+                data.tutorial.mark(tutorial::Target::BuildMenu, build_menu.rect);
+                ui.checkbox(&mut blueprints.open, format!("{} Blueprints", egui_phosphor::STAMP));
+                ui.checkbox(&mut data.queue_open, format!("{} Build Queue ({})", egui_phosphor::LIST_NUMBERS, data.build_queue.len()));
+                let disconnected = data.disconnected_count();
+                ui.checkbox(&mut data.input_settings.road_overlay, match disconnected {
+                    0 => format!("{} Road Network", egui_phosphor::ROAD_HORIZON),
+                    n => format!("{} Road Network ({} cut off)", egui_phosphor::ROAD_HORIZON, n),
+                });
+                ui.checkbox(&mut data.logistics_open, format!("{} Logistics", egui_phosphor::TRUCK));
+                ui.checkbox(&mut data.stats.open, format!("{} Statistics", egui_phosphor::CHART_LINE));
+                ui.checkbox(&mut data.land_open, format!("{} Land", egui_phosphor::MAP_TRIFOLD));
+                ui.checkbox(&mut data.market.open, format!("{} Market", egui_phosphor::STOREFRONT));
+                ui.checkbox(&mut data.loan.open, match data.loan.owed.ceil() as i32 {
+                    0 => format!("{} Bank", egui_phosphor::BANK),
+                    n => format!("{} Bank (owe {})", egui_phosphor::BANK, n),
+                });
+                ui.horizontal(|ui| {
+                    for tool in SelectTool::iter() {
+                        ui.selectable_value(&mut data.input_settings.select_tool, tool, format!("{} {}", tool.icon(), tool.name()));
+                    }
+                });
             });
             });
 
@@ -868,33 +1031,63 @@ ui.vertical(|ui| {
             .show(egui_ctx, |ui| {
                 ui.vertical(|ui| {
                 if ui.button("Home").clicked() {
+                    // save before leaving, as the last autosave could be minutes ago. the error is shown on the menu
+                    let saved = saves::save(&data);
                     menu = true;
                     data = Data::new("".to_owned());
+                    if let Err(e) = saved {
+                        data.error(e);
+                    }
+                    save_browser.mark_dirty();
                 }
                 if ui.add(egui::Button::new("Tutorial").fill(egui::Color32::from_rgb(255,127,80))).clicked() {
-                    data.guide = !data.guide;
+                    data.tutorial.hidden = !data.tutorial.hidden;
                 };
+                // this zooms the land. the size of the menus is the UI scale in the settings
                 ui.horizontal(|ui|{
-                    if ui.small_button(egui_phosphor::MAGNIFYING_GLASS_PLUS).clicked() {
-                        data.ui_scale *= 1.05;
+                    if ui.small_button(egui_phosphor::MAGNIFYING_GLASS_PLUS).on_hover_text(settings.keys.hint(egui_ctx, "Zoom in", keys::Action::ZoomIn)).clicked() {
+                        data.zoom(1.25, egui_ctx.screen_rect().center());
+                    }
+                    if ui.small(format!("{}%", (data.view().scale*100.0).round())).on_hover_text("click to reset the zoom").clicked() {
+                        data.zoom(1.0 / data.view().scale, egui_ctx.screen_rect().center());
                     }
-                    ui.small(format!("{}%", (data.ui_scale*100.0).round()));
-                    if ui.small_button(egui_phosphor::MAGNIFYING_GLASS_MINUS).clicked() {
-                        data.ui_scale *= 0.95;
+                    if ui.small_button(egui_phosphor::MAGNIFYING_GLASS_MINUS).on_hover_text(settings.keys.hint(egui_ctx, "Zoom out", keys::Action::ZoomOut)).clicked() {
+                        data.zoom(0.8, egui_ctx.screen_rect().center());
                     };
                 });
+                if ui.small_button(format!("{} Settings", egui_phosphor::GEAR)).clicked() {
+                    settings.open = !settings.open;
+                }
                 
             });
             });
 
-            // guide button
-            guide::guide_popup(&mut data, egui_ctx);
+            blueprints.render(egui_ctx, &mut data);
+            data.render_queue(egui_ctx);
+            data.render_inspector(egui_ctx);
+            data.render_logistics(egui_ctx);
+            data.render_market(egui_ctx);
+            data.render_bank(egui_ctx);
+            data.render_land(egui_ctx);
+            data.render_stats(egui_ctx);
+            data.render_minimap(egui_ctx);
+            data.render_flows(egui_ctx);
+            settings_changed |= settings.render(egui_ctx);
+            if settings_changed {
+                settings.apply(egui_ctx);
+                if let Err(e) = settings.save() {
+                    data.error(e);
+                }
+            }
+            // the tutorial goes last so that every widget it can point at has been drawn
+            data.render_tutorial(egui_ctx);
             data.toasts.show(egui_ctx);
 
             
         });
 
         
+        data.draw_distant(settings.ui_scale, Palette::of(settings.dark_mode));
         egui_macroquad::draw();
 
         #[cfg(debug_assertions)]
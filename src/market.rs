@@ -0,0 +1,313 @@
+use std::collections::HashMap;
+
+use egui::plot::{Line, Plot, PlotPoints};
+
+use crate::{
+    building::{BuildingType, Resource},
+    logistics::stockpile_capacity,
+    resources::ResourceBag,
+    Data,
+};
+
+/// the goods that can be bought and sold, along with the price they settle back to, in tax per unit
+const GOODS: [(Resource, f32); 4] = [
+    (Resource::Wood, 1.0),
+    (Resource::Food, 1.0),
+    (Resource::Steel, 5.0),
+    (Resource::Concrete, 3.0),
+];
+
+/// how much each unit bought or sold moves the price, as a fraction of the price
+const PRICE_IMPACT: f32 = 0.002;
+/// how far prices move back towards their usual value each tick
+const PRICE_RECOVERY: f32 = 0.05;
+/// the number of ticks of prices that are kept
+const HISTORY_LEN: usize = 100;
+
+/// the cut the market takes from every trade, before shops lower it
+const BASE_FEE: f32 = 0.1;
+/// how much each shop lowers the fee
+const SHOP_DISCOUNT: f32 = 0.01;
+const MIN_FEE: f32 = 0.02;
+/// how many goods can be bought and sold each tick, before banks raise it
+const BASE_CAPACITY: i32 = 50;
+/// how many more goods can be traded each tick for every bank
+const BANK_CAPACITY: i32 = 50;
+
+/// the usual price of a good, or `None` if it cannot be traded
+pub fn base_price(resource: Resource) -> Option<f32> {
+    GOODS.iter().find(|x| x.0 == resource).map(|x| x.1)
+}
+
+/// the prices of goods, which fall as goods are sold and rise as they are bought
+#[derive(Savefile)]
+pub struct Market {
+    /// the current price of each good, in tax per unit
+    pub prices: HashMap<Resource, f32>,
+    /// the price of each good at the end of each recent tick, oldest first
+    pub history: HashMap<Resource, Vec<f32>>,
+    /// sell goods that are made when storage is full instead of throwing them away
+    pub auto_sell: bool,
+    /// the number of goods bought and sold this tick
+    #[savefile_ignore]
+    traded: i32,
+    #[savefile_ignore]
+    pub open: bool,
+    /// the amount to buy or sell with the buttons in the market window. it is not saved, so loading gives
+    /// it the same default as a new market
+    #[savefile_ignore]
+    #[savefile_default_val = "10"]
+    amount: i32,
+}
+
+impl Default for Market {
+    fn default() -> Self {
+        Market {
+            prices: GOODS.iter().copied().collect(),
+            history: HashMap::new(),
+            auto_sell: false,
+            traded: 0,
+            open: false,
+            amount: 10,
+        }
+    }
+}
+
+impl Market {
+    /// the current price of a good, in tax per unit
+    pub fn price(&self, resource: Resource) -> f32 {
+        self.prices
+            .get(&resource)
+            .copied()
+            .or_else(|| base_price(resource))
+            .unwrap_or(0.0)
+    }
+
+    /// move the price of a good after `amount` of it has been bought, or sold if it is negative
+    fn trade(&mut self, resource: Resource, amount: i32) {
+        let base = match base_price(resource) {
+            Some(b) => b,
+            None => return,
+        };
+        let price = self.price(resource) * (1.0 + PRICE_IMPACT * amount as f32).max(0.5);
+        self.prices.insert(resource, price.clamp(base * 0.2, base * 5.0));
+        self.traded += amount.abs();
+    }
+
+    /// called every economy tick to let prices settle back and to record them
+    fn on_tick(&mut self) {
+        self.traded = 0;
+        for (r, base) in GOODS {
+            let price = self.price(r);
+            let price = price + (base - price) * PRICE_RECOVERY;
+            self.prices.insert(r, price);
+            let history = self.history.entry(r).or_default();
+            history.push(price);
+            if history.len() > HISTORY_LEN {
+                history.remove(0);
+            }
+        }
+    }
+}
+
+impl Data {
+    /// count the working buildings of a type across the whole city
+    fn count_working(&self, building_type: BuildingType) -> i32 {
+        self.income.count(building_type)
+    }
+
+    /// the cut the market takes from every trade. every shop lowers it
+    fn market_fee(&self) -> f32 {
+        (BASE_FEE - SHOP_DISCOUNT * self.count_working(BuildingType::Shop) as f32).max(MIN_FEE)
+    }
+
+    /// how many goods can be bought and sold each tick. every bank raises it
+    fn market_capacity(&self) -> i32 {
+        BASE_CAPACITY + BANK_CAPACITY * self.count_working(BuildingType::Bank)
+    }
+
+    /// the most tax that can be held
+    fn tax_cap(&self) -> i64 {
+        let (storage, cash_storage) = self.storage_caps();
+        storage + cash_storage
+    }
+
+    /// the most of a good that can be held where the market delivers it
+    fn goods_cap(&self) -> i64 {
+        match self.logistics {
+            true => self
+                .home_tile()
+                .and_then(|p| self.tiles.get(&p))
+                .map(stockpile_capacity)
+                .unwrap_or(0),
+            false => self.storage_caps().0,
+        }
+    }
+
+    /// sell goods for tax. with logistics turned on the goods are taken from the home tile
+    pub fn sell(&mut self, resource: Resource, amount: i32) -> Result<(), String> {
+        let home = self.home_tile().ok_or("there is no land to trade from")?;
+        if amount > self.market_capacity() - self.market.traded {
+            return Err(format!(
+                "the market can only take {} more goods this tick. banks let it take more",
+                (self.market_capacity() - self.market.traded).max(0)
+            ));
+        }
+        let earned = (self.market.price(resource) * amount as f32 * (1.0 - self.market_fee())).floor() as i64;
+        let tax_cap = self.tax_cap();
+        self.with_funds(home, |_, funds| {
+            if funds.get(Resource::Tax) + earned > tax_cap {
+                return Err(format!("there is not enough room to store {} more tax", earned));
+            }
+            funds
+                .pay(&ResourceBag::of(&[(resource, amount)]))
+                .map_err(|r| format!("not enough {}", r.name()))?;
+            funds.add(Resource::Tax, earned);
+            Ok(())
+        })
+        .unwrap_or(Ok(()))?;
+        self.market.trade(resource, -amount);
+        Ok(())
+    }
+
+    /// buy goods with tax. with logistics turned on the goods are delivered to the home tile
+    pub fn buy(&mut self, resource: Resource, amount: i32) -> Result<(), String> {
+        let home = self.home_tile().ok_or("there is no land to trade from")?;
+        if amount > self.market_capacity() - self.market.traded {
+            return Err(format!(
+                "the market can only sell {} more goods this tick. banks let it sell more",
+                (self.market_capacity() - self.market.traded).max(0)
+            ));
+        }
+        let cost = (self.market.price(resource) * amount as f32 * (1.0 + self.market_fee())).ceil() as i64;
+        let goods_cap = self.goods_cap();
+        self.with_funds(home, |_, funds| {
+            if funds.get(resource) + amount as i64 > goods_cap {
+                return Err(format!("there is not enough room to store {} more {}", amount, resource.name()));
+            }
+            let mut price = ResourceBag::default();
+            price.set(Resource::Tax, cost);
+            funds
+                .pay(&price)
+                .map_err(|_| format!("not enough Tax, it costs {}", cost))?;
+            funds.add(resource, amount as i64);
+            Ok(())
+        })
+        .unwrap_or(Ok(()))?;
+        self.market.trade(resource, amount);
+        Ok(())
+    }
+
+    /// the market part of an economy tick. prices settle back, and with auto sell turned on the goods
+    /// that were made but did not fit in storage are sold. `before` is the totals from before production
+    pub fn market_tick(&mut self, before: &ResourceBag) {
+        self.market.on_tick();
+        if !self.market.auto_sell {
+            return;
+        }
+        let totals = self.totals();
+        let fee = self.market_fee();
+        let tax_cap = self.tax_cap();
+        for (r, _) in GOODS {
+            let gained = totals.get(r) - before.get(r);
+            let overflow = (self.per_tick.get(r) - gained).min((self.market_capacity() - self.market.traded) as i64) as i32;
+            if overflow <= 0 {
+                continue;
+            }
+            let earned = (self.market.price(r) * overflow as f32 * (1.0 - fee)).floor() as i64;
+            self.resources.add_up_to(Resource::Tax, earned, tax_cap);
+            self.market.trade(r, -overflow);
+        }
+    }
+
+    /// render the market window
+    pub fn render_market(&mut self, egui_ctx: &egui::Context) {
+        if !self.market.open {
+            return;
+        }
+        let funds = match self.home_tile() {
+            Some(p) => self.funds(p),
+            None => self.resources,
+        };
+        let fee = self.market_fee();
+        let capacity = self.market_capacity();
+        let mut trade = None;
+        let mut open = self.market.open;
+        egui::Window::new(format!("{} Market", egui_phosphor::STOREFRONT))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                ui.small(format!(
+                    "fee {}%, each {} {} lowers it",
+                    (fee * 100.0).round(),
+                    BuildingType::Shop.symbol(),
+                    BuildingType::Shop.name()
+                ));
+                ui.small(format!(
+                    "{} of {} goods traded this tick, each {} {} adds {}",
+                    self.market.traded,
+                    capacity,
+                    BuildingType::Bank.symbol(),
+                    BuildingType::Bank.name(),
+                    BANK_CAPACITY
+                ));
+                if self.logistics {
+                    ui.small("goods are traded from the stockpile of the home tile");
+                }
+                ui.checkbox(&mut self.market.auto_sell, "sell what does not fit in storage")
+                    .on_hover_text("goods made while storage is full are sold at the market price instead of being thrown away");
+                ui.horizontal(|ui| {
+                    ui.label("amount");
+                    ui.add(egui::DragValue::new(&mut self.market.amount).clamp_range(1..=1000));
+                });
+                ui.separator();
+
+                egui::Grid::new("market").striped(true).show(ui, |ui| {
+                    for (r, base) in GOODS {
+                        let price = self.market.price(r);
+                        let history = self.market.history.get(&r).cloned().unwrap_or_default();
+                        let trend = match history.first() {
+                            Some(old) if price > old * 1.01 => egui_phosphor::TREND_UP,
+                            Some(old) if price < old * 0.99 => egui_phosphor::TREND_DOWN,
+                            _ => egui_phosphor::MINUS,
+                        };
+                        ui.label(format!("{} {}", r.symbol(), r.name()));
+                        ui.label(format!("{}", funds.get(r)));
+                        ui.label(format!("{} {:.2}", trend, price))
+                            .on_hover_text(format!("usually {:.2}", base));
+                        Plot::new(format!("price history {}", r.name()))
+                            .width(120.0)
+                            .height(30.0)
+                            .show_axes([false, false])
+                            .show_x(false)
+                            .show_y(false)
+                            .allow_drag(false)
+                            .allow_zoom(false)
+                            .allow_scroll(false)
+                            .allow_boxed_zoom(false)
+                            .include_y(0.0)
+                            .show(ui, |plot| plot.line(Line::new(PlotPoints::from_ys_f32(&history))));
+                        let amount = self.market.amount;
+                        let sell_for = (price * amount as f32 * (1.0 - fee)).floor();
+                        let buy_for = (price * amount as f32 * (1.0 + fee)).ceil();
+                        if ui.button("Sell").on_hover_text(format!("get {}{}", Resource::Tax.symbol(), sell_for)).clicked() {
+                            trade = Some((r, -amount));
+                        }
+                        if ui.button("Buy").on_hover_text(format!("costs {}{}", Resource::Tax.symbol(), buy_for)).clicked() {
+                            trade = Some((r, amount));
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+        self.market.open = open;
+        let result = match trade {
+            Some((r, n)) if n < 0 => self.sell(r, -n),
+            Some((r, n)) => self.buy(r, n),
+            None => Ok(()),
+        };
+        if let Err(e) = result {
+            self.info(e);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+use egui::{Align2, Color32, ColorImage, Order, Pos2, Rect, Sense, Stroke, TextureHandle, TextureOptions, Vec2};
+
+use crate::{
+    building::BuildingType,
+    tile::{Pos, Tile},
+    Data,
+};
+
+/// the width and height of the minimap on the screen
+const MAP_SIZE: f32 = 160.0;
+
+/// the width and height of a tile in the minimap texture, in pixels
+const TILE_PIXELS: usize = 4;
+
+const VIEWPORT_COLOR: Color32 = Color32::from_rgb(220, 60, 60);
+const FOR_SALE_COLOR: Color32 = Color32::from_rgb(160, 160, 160);
+
+/// the most common building on a tile, leaving out empty ground
+fn dominant(tile: &Tile) -> Option<BuildingType> {
+    tile.buildings
+        .iter()
+        .filter(|x| x.0 != &BuildingType::Ground && x.1 > &0)
+        .max_by_key(|x| (x.1, x.0.name()))
+        .map(|x| *x.0)
+}
+
+/// the minimap drawn into a texture, so that the tiles are only gone through again when the land or buildings change
+pub struct Minimap {
+    /// the tile in the top left corner of the map
+    min: Pos,
+    /// the width and height of a tile on the minimap
+    scale: f32,
+    texture: TextureHandle,
+}
+
+impl Minimap {
+    /// draw every owned tile coloured by its most common building, and an outline for the land that can be bought
+    fn new(data: &Data, egui_ctx: &egui::Context) -> Minimap {
+        // the area covered by the map, in tiles
+        let positions = data.tiles.keys().chain(data.new_pos.iter());
+        let (mut min, mut max) = (Pos::new(0, 0), Pos::new(0, 0));
+        for p in positions {
+            min = Pos::new(min.x.min(p.x), min.y.min(p.y));
+            max = Pos::new(max.x.max(p.x), max.y.max(p.y));
+        }
+        let tiles_across = (max.x - min.x + 1).max(max.y - min.y + 1) as usize;
+        let size = tiles_across * TILE_PIXELS;
+        let mut image = ColorImage::new([size, size], Color32::TRANSPARENT);
+        let mut fill = |p: &Pos, color: Color32, outline: bool| {
+            let left = (p.x - min.x) as usize * TILE_PIXELS;
+            let top = (p.y - min.y) as usize * TILE_PIXELS;
+            // the last row and column are left clear, so that there is a gap between tiles
+            let last = TILE_PIXELS - 2;
+            for x in 0..=last {
+                for y in 0..=last {
+                    if !outline || x == 0 || y == 0 || x == last || y == last {
+                        image.pixels[(top + y) * size + left + x] = color;
+                    }
+                }
+            }
+        };
+        for p in &data.new_pos {
+            fill(p, FOR_SALE_COLOR, true);
+        }
+        for (p, tile) in &data.tiles {
+            fill(p, dominant(tile).unwrap_or(BuildingType::Ground).color(), false);
+        }
+        Minimap {
+            min,
+            scale: MAP_SIZE / tiles_across as f32,
+            texture: egui_ctx.load_texture("minimap", image, TextureOptions::NEAREST),
+        }
+    }
+}
+
+impl Data {
+    /// render the minimap in the top right corner. it shows every owned tile coloured by its most common
+    /// building, the land that can be bought, and the part of the world on the screen. clicking or
+    /// dragging on it moves the view there. the tiles are only drawn again after the land or buildings changed
+    pub fn render_minimap(&mut self, egui_ctx: &egui::Context) {
+        if self.minimap.is_none() {
+            self.minimap = Some(Minimap::new(self, egui_ctx));
+        }
+        let Some(minimap) = &self.minimap else {
+            return;
+        };
+        let (min, scale, texture) = (minimap.min, minimap.scale, minimap.texture.id());
+        let screen = egui_ctx.screen_rect();
+        let view = self.view();
+
+        egui::Area::new("minimap")
+            .anchor(Align2::RIGHT_TOP, [-10.0, 10.0])
+            .order(Order::Foreground)
+            .show(egui_ctx, |ui| {
+                egui::Frame::default()
+                    .fill(ui.visuals().extreme_bg_color)
+                    .stroke(Stroke::new(1.0, Color32::GRAY))
+                    .show(ui, |ui| {
+                        let (response, painter) =
+                            ui.allocate_painter(Vec2::splat(MAP_SIZE), Sense::click_and_drag());
+                        let origin = response.rect.min;
+                        // a point in tiles to a point on the minimap
+                        let to_map = |x: f32, y: f32| {
+                            origin + Vec2::new((x - min.x as f32) * scale, (y - min.y as f32) * scale)
+                        };
+
+                        painter.image(
+                            texture,
+                            Rect::from_min_size(origin, Vec2::splat(MAP_SIZE)),
+                            Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0)),
+                            Color32::WHITE,
+                        );
+
+                        // the part of the world that is on the screen
+                        let view_min = view.to_world(screen.min);
+                        let view_max = view.to_world(screen.max);
+                        let viewport = Rect::from_min_max(to_map(view_min.x, view_min.y), to_map(view_max.x, view_max.y));
+                        painter
+                            .with_clip_rect(response.rect)
+                            .rect_stroke(viewport, 0.0, Stroke::new(1.5, VIEWPORT_COLOR));
+
+                        if let Some(pointer) = response.hover_pos() {
+                            let at = (pointer - origin) / scale;
+                            let pos = Pos::new(
+                                (at.x.floor() as i32) + min.x,
+                                (at.y.floor() as i32) + min.y,
+                            );
+                            let text = match self.tiles.get(&pos) {
+                                Some(t) => match dominant(t) {
+                                    Some(b) => format!("{} mostly {} {}", pos.to_string(), b.symbol(), b.name()),
+                                    None => format!("{} empty", pos.to_string()),
+                                },
+                                None if self.new_pos.contains(&pos) => format!("{} for sale", pos.to_string()),
+                                None => pos.to_string(),
+                            };
+                            response.clone().on_hover_text(text);
+                        }
+
+                        if response.clicked() || response.dragged() {
+                            if let Some(pointer) = response.interact_pointer_pos() {
+                                // put the point that was clicked in the middle of the screen
+                                let at = (pointer - origin) / scale + Vec2::new(min.x as f32, min.y as f32);
+                                let centre = screen.center();
+                                self.screen_offset =
+                                    (centre.x - at.x * view.tile_size(), centre.y - at.y * view.tile_size());
+                            }
+                        }
+                    });
+            });
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::HashMap;
+
+use crate::{
+    building::{Building, BuildingType},
+    history::Action,
+    logistics::{merge_funds, split_funds},
+    resources::ResourceBag,
+    tile::{Pos, Tile},
+    Data, EditTool, SelectTool,
+};
+
+/// a drag across the land with the build or remove tool
+pub struct Stroke {
+    /// the world cell the drag started on
+    start: Pos,
+    /// every world cell the drag has passed over, in order
+    cells: Vec<Pos>,
+    /// fill the rectangle between the start and the pointer instead of following the pointer
+    rect: bool,
+    /// the pointer has moved far enough that egui will not treat this as a click
+    dragged: bool,
+}
+
+impl Stroke {
+    /// the cells that the stroke will paint
+    fn cells(&self, end: Pos) -> Vec<Pos> {
+        if !self.rect {
+            return self.cells.clone();
+        }
+        let mut cells = vec![];
+        for y in self.start.y.min(end.y)..=self.start.y.max(end.y) {
+            for x in self.start.x.min(end.x)..=self.start.x.max(end.x) {
+                cells.push(Pos::new(x, y));
+            }
+        }
+        cells
+    }
+}
+
+/// what painting a set of cells did or would do
+struct PaintResult {
+    /// the building that each changed cell ended up with
+    placed: HashMap<Pos, BuildingType>,
+    /// how much of each resource was gained, negative if it was spent
+    resources: ResourceBag,
+    /// the resources gained on each tile
+    by_tile: HashMap<Pos, ResourceBag>,
+    /// the number of cells that could not be painted
+    failed: usize,
+}
+
+/// paint a tool over world cells on a set of tiles. cells on land that is not owned are skipped.
+/// in plan mode the buildings are planned instead of being built. with `logistics` turned on the
+/// goods for each cell come from the stockpile of its tile
+fn paint(
+    tiles: &mut HashMap<Pos, Tile>,
+    resources: &mut ResourceBag,
+    cells: &[Pos],
+    tool: &EditTool,
+    select_tool: SelectTool,
+    logistics: bool,
+) -> PaintResult {
+    let mut result = PaintResult {
+        placed: HashMap::new(),
+        resources: ResourceBag::default(),
+        by_tile: HashMap::new(),
+        failed: 0,
+    };
+    let building = match tool {
+        EditTool::Build(b) => b.clone(),
+        _ => Building::new(&BuildingType::Ground),
+    };
+    for cell in cells {
+        let (tile_pos, local) = cell.split_world_cell();
+        let tile = match tiles.get_mut(&tile_pos) {
+            Some(t) => t,
+            None => {
+                result.failed += 1;
+                continue;
+            }
+        };
+        let current = tile.land[local.x as usize][local.y as usize].building_type;
+        if current == building.building_type {
+            continue;
+        }
+        if select_tool == SelectTool::Plan {
+            tile.planned.insert(local, building.building_type);
+            result.placed.insert(*cell, building.building_type);
+            continue;
+        }
+        // painting only builds on empty ground, it does not replace other buildings
+        if building.building_type != BuildingType::Ground && current != BuildingType::Ground {
+            result.failed += 1;
+            continue;
+        }
+        let mut funds = merge_funds(logistics, resources, &tile.stock);
+        let old_funds = funds;
+        if tile.place(local, building.clone(), &mut funds) {
+            result.placed.insert(*cell, building.building_type);
+        } else {
+            result.failed += 1;
+        }
+        let diff = funds - old_funds;
+        if !diff.is_empty() {
+            result.resources += diff;
+            *result.by_tile.entry(tile_pos).or_default() += diff;
+        }
+        split_funds(logistics, funds, resources, &mut tile.stock);
+    }
+    // plans are paid for when they are built, so show what they will cost
+    if select_tool == SelectTool::Plan {
+        for b in result.placed.values() {
+            result.resources -= ResourceBag::of(&b.cost());
+        }
+    }
+    result
+}
+
+impl Data {
+    /// the world cell under a point on the screen
+    fn cell_at(&self, pos: egui::Pos2) -> Pos {
+        let view = self.view();
+        let at = view.to_world(pos);
+        let (x, y) = (at.x.floor() as i32, at.y.floor() as i32);
+        let cell_x = ((at.x - x as f32) * view.tile_size() / view.cell_size()) as i32;
+        let cell_y = ((at.y - y as f32) * view.tile_size() / view.cell_size()) as i32;
+        Pos::world_cell(Pos::new(x, y), Pos::new(cell_y.clamp(0, 7), cell_x.clamp(0, 7)))
+    }
+
+    /// start, extend and finish drags with the build and remove tools. a plain drag paints every
+    /// cell it passes over, and a drag with shift held fills a rectangle. nothing is changed until
+    /// the mouse is released, and until then the cells and the total cost are previewed.
+    /// `hovered` is the cell the pointer is over, if it is over a tile
+    pub fn update_stroke(&mut self, egui_ctx: &egui::Context, hovered: Option<Pos>) {
+        if !matches!(self.input_settings.edit_tool, EditTool::Build(_) | EditTool::Remove)
+            || self.input_settings.select_tool == SelectTool::Inspect
+        {
+            self.input_settings.stroke = None;
+            return;
+        }
+        let (pressed, down, dragging, shift, cancel, pointer) = egui_ctx.input(|i| {
+            (
+                i.pointer.primary_pressed(),
+                i.pointer.primary_down(),
+                // egui stops treating a press as a click once the pointer moves further than this
+                match (i.pointer.press_origin(), i.pointer.hover_pos()) {
+                    (Some(a), Some(b)) => a.distance(b) > 6.0,
+                    _ => false,
+                },
+                i.modifiers.shift,
+                i.key_pressed(egui::Key::Escape),
+                i.pointer.hover_pos(),
+            )
+        });
+
+        if pressed && !self.popup_hover {
+            if let Some(cell) = hovered {
+                self.input_settings.stroke = Some(Stroke {
+                    start: cell,
+                    cells: vec![cell],
+                    rect: shift,
+                    dragged: false,
+                });
+            }
+        }
+        if cancel {
+            self.input_settings.stroke = None;
+        }
+        let mut stroke = match self.input_settings.stroke.take() {
+            Some(s) => s,
+            None => return,
+        };
+        let end = pointer.map(|p| self.cell_at(p)).unwrap_or(stroke.start);
+        stroke.dragged |= dragging;
+        if !stroke.rect && stroke.cells.last() != Some(&end) && !stroke.cells.contains(&end) {
+            stroke.cells.push(end);
+        }
+        let cells = stroke.cells(end);
+
+        if !down {
+            // a press and release without moving is a click, which the tile handles itself
+            if stroke.dragged {
+                self.apply_stroke(&cells);
+            }
+            return;
+        }
+
+        // work out what the stroke would do on copies of the tiles it covers
+        let mut tiles: HashMap<Pos, Tile> = cells
+            .iter()
+            .map(|x| x.split_world_cell().0)
+            .filter_map(|x| self.tiles.get(&x).map(|t| (x, t.clone())))
+            .collect();
+        let mut resources = self.resources;
+        let result = paint(
+            &mut tiles,
+            &mut resources,
+            &cells,
+            &self.input_settings.edit_tool,
+            self.input_settings.select_tool,
+            self.logistics,
+        );
+        self.input_settings.preview = result.placed;
+
+        if stroke.dragged {
+            egui::show_tooltip_at_pointer(egui_ctx, egui::Id::new("paint cost"), |ui| {
+                ui.label(format!(
+                    "{} {}",
+                    self.input_settings.preview.len(),
+                    match self.input_settings.select_tool {
+                        SelectTool::Plan => "planned",
+                        _ => "changed",
+                    }
+                ));
+                if !result.resources.is_empty() {
+                    ui.label(
+                        result
+                            .resources
+                            .iter()
+                            .map(|x| format!("{}{} ", x.0.symbol(), x.1))
+                            .collect::<String>(),
+                    );
+                }
+                if result.failed > 0 {
+                    ui.colored_label(
+                        egui::Color32::from_rgb(200, 60, 60),
+                        format!("{} cells cannot be built on", result.failed),
+                    );
+                }
+                ui.small("release to confirm, escape to cancel");
+            });
+        }
+        self.input_settings.stroke = Some(stroke);
+    }
+
+    /// paint the current tool over a set of world cells and record it so that it can be undone
+    fn apply_stroke(&mut self, cells: &[Pos]) {
+        let tile_positions = cells
+            .iter()
+            .map(|x| x.split_world_cell().0)
+            .filter(|x| self.tiles.contains_key(x))
+            .collect::<std::collections::HashSet<Pos>>();
+        let before = tile_positions
+            .iter()
+            .map(|x| (*x, self.tiles[x].snapshot()))
+            .collect::<HashMap<Pos, _>>();
+
+        let mut result = paint(
+            &mut self.tiles,
+            &mut self.resources,
+            cells,
+            &self.input_settings.edit_tool,
+            self.input_settings.select_tool,
+            self.logistics,
+        );
+
+        let mut actions = vec![];
+        for (pos, state) in before {
+            let after = self.tiles[&pos].snapshot();
+            if after == state {
+                continue;
+            }
+            // each tile records what was spent on it, so that undoing gives goods back to the right stockpile
+            actions.push(Action::Edit {
+                tile: pos,
+                before: Box::new(state),
+                after: Box::new(after),
+                resources: result.by_tile.remove(&pos).unwrap_or_default(),
+            });
+            self.add_buildings(pos.get_adjacent());
+        }
+        if !actions.is_empty() {
+            self.history.push(Action::Group(actions));
+        }
+        if result.failed > 0 && self.input_settings.select_tool != SelectTool::Plan {
+            self.warn(format!("{} cells could not be built on", result.failed));
+        }
+    }
+}
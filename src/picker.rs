@@ -0,0 +1,314 @@
+use egui::RichText;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::{
+    building::{Building, BuildingType, Resource},
+    resources::ResourceBag,
+    tile::Invalid,
+    tutorial::Target,
+    Data, EditTool,
+};
+
+/// the number of buildings kept in the recently used row
+const RECENT: usize = 8;
+
+/// how the buildings in the picker are ordered
+#[derive(Savefile, PartialEq, Eq, Copy, Clone, EnumIter, Default)]
+pub enum Sort {
+    /// in the order the stages unlock them
+    #[default]
+    Stage,
+    Name,
+    /// cheapest first, adding up every resource in the cost
+    Cost,
+}
+
+impl Sort {
+    fn name(&self) -> &'static str {
+        match self {
+            Sort::Stage => "stage",
+            Sort::Name => "name",
+            Sort::Cost => "cost",
+        }
+    }
+}
+
+/// the building menu. favourites and recent buildings are saved with the city, the search is not
+#[derive(Savefile, Clone, Default)]
+pub struct Picker {
+    pub favourites: Vec<BuildingType>,
+    /// the buildings picked most recently, newest first
+    pub recent: Vec<BuildingType>,
+    pub sort: Sort,
+    #[savefile_ignore]
+    search: String,
+    /// only show buildings that can be paid for now
+    #[savefile_ignore]
+    affordable: bool,
+    /// only show buildings that make this resource
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    produces: Option<Resource>,
+    /// only show buildings that could be put on the cell the pointer was last over
+    #[savefile_ignore]
+    valid_here: bool,
+}
+
+impl Picker {
+    fn toggle_favourite(&mut self, building: BuildingType) {
+        match self.favourites.iter().position(|x| *x == building) {
+            Some(i) => {
+                self.favourites.remove(i);
+            }
+            None => self.favourites.push(building),
+        }
+    }
+}
+
+/// the cost, output and neighbours of a building, for its hover text
+fn details(building: &Building) -> String {
+    let list = |x: &[BuildingType]| x.iter().map(|b| b.symbol().replace("  ", "[empty space]")).collect::<String>();
+    let mut text = format!(
+        "{}\ncosts {}",
+        building.building_type.name(),
+        building.cost.iter().map(|x| format!("{}{} ", x.0.symbol(), x.1)).collect::<String>()
+    );
+    let output = building.building_type.output();
+    if !output.is_empty() {
+        text += &format!("\nmakes {}", output.iter().map(|x| format!("{}{} ", x.0.symbol(), x.1)).collect::<String>());
+    }
+    if !building.required_adj.is_empty() {
+        text += &format!("\nmust be placed next to: {}", list(&building.required_adj));
+    }
+    text += &format!("\ncan be next to: {}", list(&building.optional_adj));
+    if !building.tile_adj.is_empty() {
+        text += &format!("\nmust be on the land grid as: {}", list(&building.tile_adj));
+    }
+    text
+}
+
+/// a button for a building. the star marks favourites, and right clicking adds or removes one.
+/// returns `Some(true)` if it was clicked and `Some(false)` if it was added to or removed from the favourites,
+/// along with where the button is
+fn building_button(ui: &mut egui::Ui, b: &BuildingType, selected: bool, favourite: bool, compact: bool) -> (Option<bool>, egui::Rect) {
+    let star = match favourite {
+        true => egui_phosphor::STAR,
+        false => "",
+    };
+    let text = match compact {
+        true => format!("{}{}", b.symbol(), star),
+        false => format!("{} {} {}", b.symbol(), b.name(), star),
+    };
+    let response = ui.selectable_label(selected, text).on_hover_text(details(&Building::new(b)));
+    let mut result = response.clicked().then_some(true);
+    let rect = response.rect;
+    response.context_menu(|ui| {
+        let text = match favourite {
+            true => "remove from favourites",
+            false => "add to favourites",
+        };
+        if ui.button(text).clicked() {
+            result = Some(false);
+            ui.close_menu();
+        }
+    });
+    (result, rect)
+}
+
+impl Data {
+    /// the buildings in the stages that are unlocked, in the order the stages unlock them
+    fn unlocked_buildings(&self) -> Vec<BuildingType> {
+        self.stage
+            .iter()
+            .filter(|s| s.enabled)
+            .flat_map(|s| s.buildings.iter().copied())
+            .collect()
+    }
+
+    /// check if a building could go on the cell the pointer was last over, leaving out whether it can be paid for
+    fn valid_on_hovered(&self, building: &Building) -> bool {
+        let Some(cell) = self.input_settings.hovered else {
+            return false;
+        };
+        let (pos, cell) = cell.split_world_cell();
+        match self.tiles.get(&pos) {
+            Some(tile) => matches!(
+                tile.can_place(cell, building, &ResourceBag::default()),
+                Ok(()) | Err(Invalid::Unaffordable(_))
+            ),
+            None => false,
+        }
+    }
+
+    /// the buildings that pass the search and filters, in the chosen order
+    fn picked_buildings(&self) -> Vec<BuildingType> {
+        let totals = self.totals();
+        let search = self.picker.search.to_lowercase();
+        let mut buildings = self
+            .unlocked_buildings()
+            .into_iter()
+            .filter(|b| search.is_empty() || b.name().to_lowercase().contains(&search) || b.symbol().contains(&search))
+            .filter(|b| !self.picker.affordable || totals.can_afford(&ResourceBag::of(&b.cost())))
+            .filter(|b| match self.picker.produces {
+                Some(r) => b.output().iter().any(|x| x.0 == r && x.1 > 0),
+                None => true,
+            })
+            .filter(|b| !self.picker.valid_here || self.valid_on_hovered(&Building::new(b)))
+            .collect::<Vec<_>>();
+        match self.picker.sort {
+            Sort::Stage => {}
+            Sort::Name => buildings.sort_by_key(|b| b.name()),
+            Sort::Cost => buildings.sort_by_key(|b| b.cost().iter().map(|x| x.1 as i64).sum::<i64>()),
+        }
+        buildings
+    }
+
+    /// add the building being placed to the front of the recently used row
+    fn note_recent(&mut self) {
+        if let EditTool::Build(b) = &self.input_settings.edit_tool {
+            let b = b.building_type;
+            if b != BuildingType::Ground && self.picker.recent.first() != Some(&b) {
+                self.picker.recent.retain(|x| *x != b);
+                self.picker.recent.insert(0, b);
+                self.picker.recent.truncate(RECENT);
+            }
+        }
+    }
+
+    /// render the building menu
+    pub fn render_picker(&mut self, egui_ctx: &egui::Context) {
+        self.note_recent();
+        let mut open = self.popup;
+        let mut picked = None;
+        let mut favourite = None;
+        let mut unlock = None;
+        // where each building button was drawn, so that the tutorial can point at them
+        let mut marks = vec![];
+        let buildings = self.picked_buildings();
+        let unlocked = self.unlocked_buildings();
+        let selected = match &self.input_settings.edit_tool {
+            EditTool::Build(b) => Some(b.building_type),
+            _ => None,
+        };
+        let response = egui::Window::new(format!("Build: {}", &self.input_settings.edit_tool.icon()))
+            .id(egui::Id::new("popup selector"))
+            .collapsible(true)
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                let compact = self.quick_menu;
+                let favourites = self.picker.favourites.clone();
+                let mut button = |ui: &mut egui::Ui, b: &BuildingType| {
+                    let (result, rect) = building_button(ui, b, selected == Some(*b), favourites.contains(b), compact);
+                    match result {
+                        Some(true) => picked = Some(*b),
+                        Some(false) => favourite = Some(*b),
+                        None => {}
+                    }
+                    marks.push((*b, rect));
+                };
+
+                ui.horizontal(|ui| {
+                    ui.label(egui_phosphor::MAGNIFYING_GLASS);
+                    ui.add(egui::TextEdit::singleline(&mut self.picker.search).hint_text("search").desired_width(120.0));
+                    ui.checkbox(&mut self.quick_menu, "compact");
+                });
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut self.picker.affordable, "affordable");
+                    ui.add_enabled(self.input_settings.hovered.is_some(), egui::Checkbox::new(&mut self.picker.valid_here, "fits last hovered cell"));
+                });
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("picker produces")
+                        .selected_text(match self.picker.produces {
+                            Some(r) => format!("makes {} {}", r.symbol(), r.name()),
+                            None => "makes anything".to_owned(),
+                        })
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut self.picker.produces, None, "anything");
+                            for r in Resource::iter() {
+                                ui.selectable_value(&mut self.picker.produces, Some(r), format!("{} {}", r.symbol(), r.name()));
+                            }
+                        });
+                    egui::ComboBox::from_id_source("picker sort")
+                        .selected_text(format!("sort by {}", self.picker.sort.name()))
+                        .show_ui(ui, |ui| {
+                            for s in Sort::iter() {
+                                ui.selectable_value(&mut self.picker.sort, s, s.name());
+                            }
+                        });
+                });
+                if ui.small_button(format!("{} Destroy", egui_phosphor::ERASER)).clicked() {
+                    self.input_settings.edit_tool = EditTool::Remove;
+                }
+
+                let favourites = favourites.iter().filter(|b| unlocked.contains(b)).copied().collect::<Vec<_>>();
+                let recent = self.picker.recent.iter().filter(|b| unlocked.contains(b)).copied().collect::<Vec<_>>();
+                for (title, row) in [(format!("{} favourites", egui_phosphor::STAR), &favourites), (format!("{} recently used", egui_phosphor::CLOCK), &recent)] {
+                    if !row.is_empty() {
+                        ui.separator();
+                        ui.label(RichText::new(title).small());
+                        ui.horizontal_wrapped(|ui| {
+                            for b in row {
+                                button(ui, b);
+                            }
+                        });
+                    }
+                }
+
+                ui.separator();
+                if buildings.is_empty() {
+                    ui.label("no buildings match");
+                }
+                match compact {
+                    true => {
+                        ui.horizontal_wrapped(|ui| {
+                            for b in &buildings {
+                                button(ui, b);
+                            }
+                        });
+                    }
+                    false => {
+                        for b in &buildings {
+                            ui.horizontal(|ui| {
+                                button(ui, b);
+                                ui.small(b.cost().iter().map(|x| format!("{}{} ", x.0.symbol(), x.1)).collect::<String>());
+                            });
+                        }
+                    }
+                }
+
+                // the next stage to unlock, which can also be unlocked early
+                if let Some((i, stage)) = self.stage.iter().enumerate().find(|x| !x.1.enabled) {
+                    ui.separator();
+                    ui.label(format!("{} Stage {}", egui_phosphor::LOCK, stage.num));
+                    ui.small(format!(
+                        "unlocks at {}",
+                        stage.unlock_at.iter().map(|x| format!("{} {} {} ", x.0.symbol(), x.0.name(), x.1)).collect::<String>()
+                    ));
+                    if ui.small_button("Unlock Early").clicked() {
+                        unlock = Some(i);
+                    }
+                }
+            });
+        self.popup = open;
+
+        if let Some(r) = response {
+            if let Some(p) = egui_ctx.pointer_hover_pos() {
+                self.popup_hover = r.response.rect.signed_distance_to_pos(p) < 0.0;
+            }
+        }
+        for (b, rect) in marks {
+            self.tutorial.mark(Target::Building(b), rect);
+        }
+        if let Some(b) = picked {
+            self.input_settings.edit_tool = EditTool::Build(Building::new(&b));
+        }
+        if let Some(b) = favourite {
+            self.picker.toggle_favourite(b);
+        }
+        if let Some(i) = unlock {
+            self.stage[i].enabled = true;
+        }
+    }
+}
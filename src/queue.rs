@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    building::{Building, BuildingType, Resource},
+    resources::ResourceBag,
+    saves::format_duration,
+    tile::{Invalid, Pos},
+    Data, TICK_SECS,
+};
+
+/// whether a planned building can be built yet
+pub enum PlanStatus {
+    /// it will be built on the next tick
+    Ready,
+    /// it is waiting for resources. holds the number of ticks until enough will have been made, or
+    /// `None` if one of the resources is not being made at all
+    Waiting(Option<i32>),
+    /// it would not be valid where it is planned
+    Invalid(Invalid),
+    /// it costs more than can be stored
+    TooExpensive,
+}
+
+impl PlanStatus {
+    pub fn describe(&self) -> String {
+        match self {
+            PlanStatus::Ready => "next tick".to_owned(),
+            PlanStatus::Waiting(Some(t)) => format!("~{}", format_duration(*t as u64 * TICK_SECS)),
+            PlanStatus::Waiting(None) => "never, resources are not being made".to_owned(),
+            PlanStatus::Invalid(reason) => reason.describe(),
+            PlanStatus::TooExpensive => "needs more storage".to_owned(),
+        }
+    }
+}
+
+/// check if a cost could ever be paid with the current storage
+fn fits_in_storage(cost: &ResourceBag, storage: i64, cash_storage: i64) -> bool {
+    cost.iter().all(|x| {
+        x.1 <= match x.0 {
+            Resource::Tax | Resource::CashStorage => storage + cash_storage,
+            _ => storage,
+        }
+    })
+}
+
+impl Data {
+    /// the building planned at a world cell
+    fn planned_at(&self, cell: Pos) -> Option<BuildingType> {
+        let (tile, cell) = cell.split_world_cell();
+        self.tiles.get(&tile)?.planned.get(&cell).copied()
+    }
+
+    /// make sure that every planned building is in the build queue exactly once. plans that are not
+    /// in the queue yet, such as ones from older saves, go on the end
+    pub fn sync_queue(&mut self) {
+        let mut seen = HashSet::new();
+        let mut queue = std::mem::take(&mut self.build_queue);
+        queue.retain(|cell| self.planned_at(*cell).is_some() && seen.insert(*cell));
+
+        let mut missing = vec![];
+        for (pos, tile) in &self.tiles {
+            for cell in tile.planned.keys() {
+                let world_cell = Pos::world_cell(*pos, *cell);
+                if !seen.contains(&world_cell) {
+                    missing.push(world_cell);
+                }
+            }
+        }
+        missing.sort_by_key(|x| (x.y, x.x));
+        queue.extend(missing);
+        self.build_queue = queue;
+    }
+
+    /// bring the build queue up to date with the plans on one tile, after they were added or removed.
+    /// this only looks at the queue and that tile, so it is cheap enough to do on every change
+    pub fn sync_tile_queue(&mut self, tile_pos: Pos) {
+        let mut queue = std::mem::take(&mut self.build_queue);
+        queue.retain(|cell| cell.split_world_cell().0 != tile_pos || self.planned_at(*cell).is_some());
+
+        if let Some(tile) = self.tiles.get(&tile_pos) {
+            let queued = queue.iter().copied().collect::<HashSet<Pos>>();
+            let mut missing = tile
+                .planned
+                .keys()
+                .map(|x| Pos::world_cell(tile_pos, *x))
+                .filter(|x| !queued.contains(x))
+                .collect::<Vec<_>>();
+            missing.sort_by_key(|x| (x.y, x.x));
+            queue.extend(missing);
+        }
+        self.build_queue = queue;
+    }
+
+    /// what building a plan will cost. a plan over another building only costs the difference
+    fn plan_cost(&self, cell: Pos, building: &BuildingType) -> ResourceBag {
+        let (tile_pos, local) = cell.split_world_cell();
+        ResourceBag::of(&match self.tiles.get(&tile_pos) {
+            Some(t) => t.land[local.x as usize][local.y as usize].building_type.replace_cost(building),
+            None => building.cost(),
+        })
+    }
+
+    /// why a planned building has not been built yet
+    fn plan_status(&self, cell: Pos, building: &Building) -> PlanStatus {
+        let (tile_pos, local) = cell.split_world_cell();
+        let tile = &self.tiles[&tile_pos];
+        if building.building_type != BuildingType::Ground {
+            match tile.can_place(local, building, &self.funds(tile_pos)) {
+                Ok(_) | Err(Invalid::Unaffordable(_)) => {}
+                Err(reason) => return PlanStatus::Invalid(reason),
+            }
+        }
+        let (storage, cash_storage) = self.storage_caps();
+        if !fits_in_storage(&self.plan_cost(cell, &building.building_type), storage, cash_storage) {
+            return PlanStatus::TooExpensive;
+        }
+        PlanStatus::Ready
+    }
+
+    /// build as many planned buildings as possible, in queue order. a building that is waiting for
+    /// resources holds back the ones after it, so that they do not spend what it is waiting for.
+    /// with logistics turned on each tile spends its own stockpile, so it only holds back the ones on its tile
+    pub fn process_queue(&mut self) {
+        let mut changed = HashSet::new();
+        let mut waiting = HashSet::new();
+        for cell in self.build_queue.clone() {
+            let building = match self.planned_at(cell) {
+                Some(b) => Building::new(&b),
+                None => continue,
+            };
+            match self.plan_status(cell, &building) {
+                PlanStatus::Ready => {}
+                _ => continue,
+            }
+            let (tile_pos, local) = cell.split_world_cell();
+            if waiting.contains(&tile_pos) {
+                continue;
+            }
+            if !self.funds(tile_pos).can_afford(&self.plan_cost(cell, &building.building_type)) {
+                if !self.logistics {
+                    break;
+                }
+                waiting.insert(tile_pos);
+                continue;
+            }
+            // a plan that could not be placed stays in the queue, where `plan_status` shows why
+            let placed = self.with_funds(tile_pos, |tile, funds| {
+                let placed = tile.place(local, building, funds);
+                if placed {
+                    tile.planned.remove(&local);
+                }
+                placed
+            });
+            if placed == Some(true) {
+                changed.insert(tile_pos);
+            }
+        }
+        for pos in changed {
+            // the buildings were not placed by the player, so older actions on the tile can no longer be undone
+            self.history.forget_tile(pos);
+            self.add_buildings(pos.get_adjacent());
+        }
+    }
+
+    /// the status of every plan in the queue. resources are shared out in queue order, so the
+    /// estimate for each plan includes the cost of the plans before it that spend from the same place
+    pub fn queue_statuses(&self) -> Vec<(Pos, BuildingType, PlanStatus)> {
+        let mut needed_by_tile: HashMap<Pos, ResourceBag> = HashMap::new();
+        let mut statuses = vec![];
+        for cell in &self.build_queue {
+            let building = match self.planned_at(*cell) {
+                Some(b) => Building::new(&b),
+                None => continue,
+            };
+            let mut status = self.plan_status(*cell, &building);
+            if let PlanStatus::Ready = status {
+                // with logistics turned on each tile spends its own stockpile, otherwise every plan shares one pool
+                let pool = match self.logistics {
+                    true => cell.split_world_cell().0,
+                    false => Pos::new(0, 0),
+                };
+                let needed = needed_by_tile.entry(pool).or_default();
+                // refunds from replacing a building are not counted, as they cannot be spent on other plans first
+                *needed += self.plan_cost(*cell, &building.building_type).iter().filter(|x| x.1 > 0).collect();
+                let mut ticks = Some(0);
+                for (r, missing) in self.funds(cell.split_world_cell().0).missing(needed).iter() {
+                    let rate = self.per_tick.get(r);
+                    ticks = match (ticks, rate > 0) {
+                        (Some(t), true) => Some(t.max(((missing + rate - 1) / rate) as i32)),
+                        _ => None,
+                    };
+                }
+                if ticks != Some(0) {
+                    status = PlanStatus::Waiting(ticks);
+                }
+            }
+            statuses.push((*cell, building.building_type, status));
+        }
+        statuses
+    }
+
+    /// render the build queue window
+    pub fn render_queue(&mut self, egui_ctx: &egui::Context) {
+        if !self.queue_open {
+            return;
+        }
+        let statuses = self.queue_statuses();
+
+        let mut total = ResourceBag::default();
+        for (cell, b, _) in &statuses {
+            total += self.plan_cost(*cell, b).iter().filter(|x| x.1 > 0).collect();
+        }
+
+        let mut move_up = None;
+        let mut move_down = None;
+        let mut cancel = None;
+        let mut open = self.queue_open;
+        egui::Window::new(format!("{} Build Queue", egui_phosphor::LIST_NUMBERS))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                if statuses.is_empty() {
+                    ui.label("nothing is planned. use planning mode to plan buildings and they will be built here when they can be afforded");
+                    return;
+                }
+                ui.label(format!(
+                    "{} planned, costing {}",
+                    statuses.len(),
+                    total
+                        .iter()
+                        .filter(|x| x.1 > 0)
+                        .map(|x| format!("{}{} ", x.0.symbol(), x.1))
+                        .collect::<String>()
+                ));
+                ui.separator();
+                egui::Grid::new("build queue").striped(true).show(ui, |ui| {
+                    for (i, (cell, b, status)) in statuses.iter().enumerate() {
+                        let (tile, local) = cell.split_world_cell();
+                        ui.label(format!("{}", i + 1));
+                        ui.label(match b {
+                            BuildingType::Ground => format!("{} remove", egui_phosphor::ERASER),
+                            b => format!("{} {}", b.symbol(), b.name()),
+                        })
+                        .on_hover_text(format!(
+                            "tile {} cell ({},{})",
+                            tile.to_string(),
+                            local.y,
+                            local.x
+                        ));
+                        ui.small(status.describe());
+                        ui.horizontal(|ui| {
+                            if ui.add_enabled(i > 0, egui::Button::new(egui_phosphor::ARROW_UP).small()).clicked() {
+                                move_up = Some(i);
+                            }
+                            if ui
+                                .add_enabled(i + 1 < statuses.len(), egui::Button::new(egui_phosphor::ARROW_DOWN).small())
+                                .clicked()
+                            {
+                                move_down = Some(i);
+                            }
+                            if ui.small_button(egui_phosphor::X).on_hover_text("cancel").clicked() {
+                                cancel = Some(*cell);
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            });
+        self.queue_open = open;
+
+        // the statuses skip cells that are no longer planned, so they line up with the synced queue
+        if let Some(i) = move_up {
+            self.build_queue.swap(i, i - 1);
+        }
+        if let Some(i) = move_down {
+            self.build_queue.swap(i, i + 1);
+        }
+        if let Some(cell) = cancel {
+            let (tile, local) = cell.split_world_cell();
+            if let Some(t) = self.tiles.get_mut(&tile) {
+                t.planned.remove(&local);
+            }
+            self.sync_tile_queue(tile);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashMap;
+use std::ops::{AddAssign, Mul, Neg, Sub, SubAssign};
+
+use strum::{EnumCount, IntoEnumIterator};
+
+use crate::building::Resource;
+
+/// the map that resources used to be kept in, which old saves still hold
+pub type ResourceMap = HashMap<Resource, i32>;
+
+/// an amount of every resource. it is a fixed array indexed by the resource instead of a map, so it is
+/// cheap to copy and a missing resource is just 0. the arithmetic saturates instead of overflowing, so
+/// very long idle games cannot wrap around to negative amounts
+#[derive(Savefile, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub struct ResourceBag {
+    amounts: [i64; Resource::COUNT],
+}
+
+impl ResourceBag {
+    /// a bag from a list of resources, such as the cost of a building
+    pub fn of(list: &[(Resource, i32)]) -> ResourceBag {
+        list.iter().copied().collect()
+    }
+
+    pub fn get(&self, resource: Resource) -> i64 {
+        self.amounts[resource as usize]
+    }
+
+    pub fn set(&mut self, resource: Resource, amount: i64) {
+        self.amounts[resource as usize] = amount;
+    }
+
+    /// add to one resource, or take away if `amount` is negative
+    pub fn add(&mut self, resource: Resource, amount: i64) {
+        let n = &mut self.amounts[resource as usize];
+        *n = n.saturating_add(amount);
+    }
+
+    /// add to one resource without going over `cap`. a resource that is already over the cap keeps
+    /// what it has
+    pub fn add_up_to(&mut self, resource: Resource, amount: i64, cap: i64) {
+        let have = self.get(resource);
+        self.set(resource, have.saturating_add(amount).min(cap.max(have)));
+    }
+
+    /// the resources that are not 0, in the order they are shown in
+    pub fn iter(&self) -> impl Iterator<Item = (Resource, i64)> + '_ {
+        Resource::iter().map(|r| (r, self.get(r))).filter(|x| x.1 != 0)
+    }
+
+    /// check if every resource is 0
+    pub fn is_empty(&self) -> bool {
+        self.amounts.iter().all(|x| *x == 0)
+    }
+
+    /// add another bag, or `None` if any resource would overflow
+    pub fn checked_add(&self, other: &ResourceBag) -> Option<ResourceBag> {
+        let mut sum = *self;
+        for (a, b) in sum.amounts.iter_mut().zip(other.amounts) {
+            *a = a.checked_add(b)?;
+        }
+        Some(sum)
+    }
+
+    /// how much more of each resource is needed to pay a cost
+    pub fn missing(&self, cost: &ResourceBag) -> ResourceBag {
+        let mut missing = ResourceBag::default();
+        for (r, n) in cost.iter() {
+            missing.set(r, (n - self.get(r)).max(0));
+        }
+        missing
+    }
+
+    /// check if there is enough of every resource to pay a cost
+    pub fn can_afford(&self, cost: &ResourceBag) -> bool {
+        self.missing(cost).is_empty()
+    }
+
+    /// take a cost out of the bag. nothing is taken if there is not enough, and the first resource
+    /// that is short is returned instead
+    pub fn pay(&mut self, cost: &ResourceBag) -> Result<(), Resource> {
+        if let Some((r, _)) = self.missing(cost).iter().next() {
+            return Err(r);
+        }
+        *self -= *cost;
+        Ok(())
+    }
+
+    /// give resources back without any of them going over `cap`. resources that are already over it
+    /// keep what they have
+    pub fn refund(&mut self, amount: &ResourceBag, cap: i64) {
+        for (r, n) in amount.iter() {
+            self.add_up_to(r, n, cap);
+        }
+    }
+}
+
+impl FromIterator<(Resource, i32)> for ResourceBag {
+    fn from_iter<T: IntoIterator<Item = (Resource, i32)>>(iter: T) -> Self {
+        iter.into_iter().map(|(r, n)| (r, n as i64)).collect()
+    }
+}
+
+impl FromIterator<(Resource, i64)> for ResourceBag {
+    fn from_iter<T: IntoIterator<Item = (Resource, i64)>>(iter: T) -> Self {
+        let mut bag = ResourceBag::default();
+        for (r, n) in iter {
+            bag.add(r, n);
+        }
+        bag
+    }
+}
+
+/// used to load the resources from saves made before the bag existed
+impl From<ResourceMap> for ResourceBag {
+    fn from(map: ResourceMap) -> Self {
+        map.into_iter().collect()
+    }
+}
+
+impl AddAssign for ResourceBag {
+    fn add_assign(&mut self, other: ResourceBag) {
+        for (a, b) in self.amounts.iter_mut().zip(other.amounts) {
+            *a = a.saturating_add(b);
+        }
+    }
+}
+
+impl Sub for ResourceBag {
+    type Output = ResourceBag;
+    fn sub(mut self, other: ResourceBag) -> ResourceBag {
+        self -= other;
+        self
+    }
+}
+
+impl SubAssign for ResourceBag {
+    fn sub_assign(&mut self, other: ResourceBag) {
+        for (a, b) in self.amounts.iter_mut().zip(other.amounts) {
+            *a = a.saturating_sub(b);
+        }
+    }
+}
+
+impl Neg for ResourceBag {
+    type Output = ResourceBag;
+    fn neg(mut self) -> ResourceBag {
+        for a in self.amounts.iter_mut() {
+            *a = a.saturating_neg();
+        }
+        self
+    }
+}
+
+impl Mul<i64> for ResourceBag {
+    type Output = ResourceBag;
+    fn mul(mut self, n: i64) -> ResourceBag {
+        for a in self.amounts.iter_mut() {
+            *a = a.saturating_mul(n);
+        }
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_saturates_instead_of_overflowing() {
+        let mut bag = ResourceBag::of(&[(Resource::Food, 10)]);
+        bag.add(Resource::Food, i64::MAX);
+        assert_eq!(bag.get(Resource::Food), i64::MAX);
+        bag.add(Resource::Wood, i64::MIN);
+        bag.add(Resource::Wood, -10);
+        assert_eq!(bag.get(Resource::Wood), i64::MIN);
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        let mut big = ResourceBag::default();
+        big.set(Resource::Tax, i64::MAX - 1);
+        let mut sum = big;
+        sum += big;
+        assert_eq!(sum.get(Resource::Tax), i64::MAX);
+        assert_eq!((big * 3).get(Resource::Tax), i64::MAX);
+        assert_eq!((-big - big).get(Resource::Tax), i64::MIN);
+        assert!(big.checked_add(&big).is_none());
+        assert_eq!(big.checked_add(&ResourceBag::of(&[(Resource::Tax, 1)])).map(|x| x.get(Resource::Tax)), Some(i64::MAX));
+    }
+
+    #[test]
+    fn pay_takes_the_cost() {
+        let mut bag = ResourceBag::of(&[(Resource::Food, 10), (Resource::Wood, 5)]);
+        assert!(bag.pay(&ResourceBag::of(&[(Resource::Food, 4), (Resource::Wood, 5)])).is_ok());
+        assert_eq!(bag, ResourceBag::of(&[(Resource::Food, 6)]));
+    }
+
+    #[test]
+    fn pay_leaves_funds_untouched_when_short() {
+        let mut bag = ResourceBag::of(&[(Resource::Food, 10), (Resource::Wood, 5)]);
+        let before = bag;
+        assert!(bag.pay(&ResourceBag::of(&[(Resource::Food, 4), (Resource::Wood, 6)])) == Err(Resource::Wood));
+        assert_eq!(bag, before);
+        assert_eq!(bag.missing(&ResourceBag::of(&[(Resource::Wood, 6), (Resource::Seed, 2)])), ResourceBag::of(&[(Resource::Wood, 1), (Resource::Seed, 2)]));
+    }
+
+    #[test]
+    fn refund_stops_at_the_cap() {
+        let mut bag = ResourceBag::of(&[(Resource::Food, 90), (Resource::Wood, 150)]);
+        bag.refund(&ResourceBag::of(&[(Resource::Food, 20), (Resource::Wood, 20), (Resource::Seed, 5)]), 100);
+        // wood was already over the cap, so it keeps what it had
+        assert_eq!(bag, ResourceBag::of(&[(Resource::Food, 100), (Resource::Wood, 150), (Resource::Seed, 5)]));
+    }
+
+    /// a struct that holds its resources the way saves before version 16 did
+    #[derive(Savefile)]
+    struct OldSave {
+        resources: ResourceMap,
+    }
+
+    #[derive(Savefile)]
+    struct NewSave {
+        #[savefile_versions_as = "0..15:ResourceMap"]
+        #[savefile_versions = "16.."]
+        resources: ResourceBag,
+    }
+
+    #[test]
+    fn loads_a_version_15_map() {
+        let old = OldSave {
+            resources: ResourceMap::from([(Resource::Food, 12), (Resource::Tax, -3)]),
+        };
+        let bytes = savefile::save_to_mem(15, &old).unwrap();
+        let new: NewSave = savefile::load_from_mem(&bytes, 16).unwrap();
+        assert_eq!(new.resources, ResourceBag::of(&[(Resource::Food, 12), (Resource::Tax, -3)]));
+
+        let bytes = savefile::save_to_mem(16, &new).unwrap();
+        let again: NewSave = savefile::load_from_mem(&bytes, 16).unwrap();
+        assert_eq!(again.resources, new.resources);
+    }
+}
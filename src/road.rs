@@ -0,0 +1,300 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::{
+    building::BuildingType,
+    tile::{Pos, Tile},
+    Data,
+};
+
+impl Data {
+    /// the building on a world cell, if the land is owned
+    pub fn building_at(&self, cell: Pos) -> Option<BuildingType> {
+        let (tile, cell) = cell.split_world_cell();
+        self.tiles
+            .get(&tile)
+            .map(|t| t.land[cell.x as usize][cell.y as usize].building_type)
+    }
+
+    /// work out which roads lead back to a road hub, following asphalt across tile boundaries, and
+    /// which buildings that need a road are not next to one of them. only the tiles that changed are
+    /// looked at: the search along the roads is only redone if a road or hub on them changed, and then
+    /// only covers the roads. the buildings are checked again on the changed tiles and the tiles next
+    /// to any road that was cut off or reconnected, and those whose buildings changed have their income refreshed
+    pub fn update_roads(&mut self) {
+        if !self.income_current {
+            // after loading everything is worked out from scratch
+            self.rebuild_income();
+            self.sync_queue();
+            self.roads_dirty = self.tiles.keys().copied().collect();
+        } else if self.land_basis.as_ref() != Some(&self.land_basis()) {
+            // buying or selling land, unlocking a stage or changing the pricing moves the value of every tile
+            self.rebuild_income();
+        }
+        if self.roads_dirty.is_empty() {
+            return;
+        }
+        let dirty = std::mem::take(&mut self.roads_dirty);
+
+        let mut network_changed = false;
+        for tile_pos in &dirty {
+            let tile = self.tiles.get(tile_pos);
+            for x in 0..8 {
+                for y in 0..8 {
+                    let cell = Pos::world_cell(*tile_pos, Pos::new(x, y));
+                    let b = tile.map(|t| t.land[x as usize][y as usize].building_type);
+                    network_changed |= set_contains(&mut self.road_cells, cell, b == Some(BuildingType::Asphalt));
+                    network_changed |= set_contains(&mut self.road_hubs, cell, b.is_some_and(|b| b.is_road_hub()));
+                }
+            }
+        }
+
+        let mut affected = dirty;
+        if network_changed {
+            // search outwards along the roads from every road that touches a hub
+            let mut connected = HashSet::new();
+            let mut queue = self
+                .road_hubs
+                .iter()
+                .flat_map(|x| x.get_adjacent()[0..4].to_vec())
+                .filter(|x| self.road_cells.contains(x))
+                .collect::<VecDeque<Pos>>();
+            while let Some(cell) = queue.pop_front() {
+                if !connected.insert(cell) {
+                    continue;
+                }
+                for next in &cell.get_adjacent()[0..4] {
+                    if self.road_cells.contains(next) && !connected.contains(next) {
+                        queue.push_back(*next);
+                    }
+                }
+            }
+            // a building can be next to a road on the tile beside it
+            for cell in connected.symmetric_difference(&self.connected_roads) {
+                affected.extend(cell.get_adjacent().iter().map(|x| x.split_world_cell().0));
+            }
+            self.connected_roads = connected;
+        }
+
+        let mut changed = vec![];
+        for tile_pos in affected {
+            let Some(tile) = self.tiles.get_mut(&tile_pos) else {
+                continue;
+            };
+            let mut roads = HashMap::new();
+            let mut disconnected = HashMap::new();
+            for x in 0..8 {
+                for y in 0..8 {
+                    let anchor = Pos::new(x as i32, y as i32);
+                    let b = tile.land[x][y].building_type;
+                    if b == BuildingType::Asphalt {
+                        roads.insert(anchor, self.connected_roads.contains(&Pos::world_cell(tile_pos, anchor)));
+                    }
+                    if !b.needs_road() || tile.parts.contains_key(&anchor) {
+                        continue;
+                    }
+                    let on_road = Tile::footprint(anchor, &b)
+                        .iter()
+                        .flat_map(|x| Pos::world_cell(tile_pos, *x).get_adjacent()[0..4].to_vec())
+                        .any(|x| self.connected_roads.contains(&x));
+                    if !on_road {
+                        disconnected.insert(anchor, b);
+                    }
+                }
+            }
+            tile.roads = roads;
+            if disconnected != tile.disconnected {
+                tile.disconnected = disconnected;
+                changed.push(tile_pos);
+            }
+        }
+        for pos in changed {
+            self.refresh_income(pos);
+        }
+    }
+
+    /// check if the building covering a world cell is cut off from the road network
+    pub fn is_disconnected(&self, cell: Pos) -> bool {
+        let (tile, local) = cell.split_world_cell();
+        match self.tiles.get(&tile) {
+            Some(t) => t.disconnected.contains_key(&t.anchor(local)),
+            None => false,
+        }
+    }
+
+    /// the number of buildings that are cut off from the road network
+    pub fn disconnected_count(&self) -> usize {
+        self.income.disconnected as usize
+    }
+
+    /// check if there is a road hub anywhere in the city
+    pub fn has_road_hub(&self) -> bool {
+        !self.road_hubs.is_empty()
+    }
+
+    /// explain why a building is cut off from the road network
+    pub fn road_hint(&self, cell: Pos) -> String {
+        let hub = self.building_at(cell).map(|b| b.needs_road()).unwrap_or(false) && !self.has_road_hub();
+        match hub {
+            true => format!(
+                "not connected to the road network. build a {} {} and connect it with {} roads",
+                BuildingType::Turret.symbol(),
+                BuildingType::Turret.name(),
+                BuildingType::Asphalt.name()
+            ),
+            false => "not connected to the road network. its road does not lead back to a town centre or a runway".to_owned(),
+        }
+    }
+}
+
+/// add or take a cell out of a set so that it is in it only if `contains` is true. returns true if the set changed
+fn set_contains(set: &mut HashSet<Pos>, cell: Pos, contains: bool) -> bool {
+    match contains {
+        true => set.insert(cell),
+        false => set.remove(&cell),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+
+    use super::*;
+    use crate::{
+        building::{Building, Resource},
+        history::Action,
+        income::Income,
+    };
+
+    /// change one cell the way the player would, so that it can be undone
+    fn build(data: &mut Data, tile: Pos, cell: Pos, building_type: BuildingType) {
+        let t = data.tiles.get_mut(&tile).unwrap();
+        let before = t.snapshot();
+        let old_resources = data.resources;
+        assert!(t.place(cell, Building::new(&building_type), &mut data.resources));
+        data.history.push(Action::Edit {
+            tile,
+            before: Box::new(before),
+            after: Box::new(t.snapshot()),
+            resources: data.resources - old_resources,
+        });
+        data.add_buildings(tile.get_adjacent());
+    }
+
+    /// the buildings, income, roads and cut off buildings on a tile
+    type TileWork = (HashMap<BuildingType, i32>, Income, HashMap<Pos, bool>, HashMap<Pos, BuildingType>);
+
+    /// what each tile and the city have worked out about their buildings and roads
+    fn state(data: &Data) -> (HashMap<Pos, TileWork>, Income, HashSet<Pos>) {
+        let tiles = data
+            .tiles
+            .iter()
+            .map(|(pos, t)| (*pos, (t.buildings.clone(), t.income.clone(), t.roads.clone(), t.disconnected.clone())))
+            .collect();
+        (tiles, data.income.clone(), data.connected_roads.clone())
+    }
+
+    /// check that what was kept up to date one change at a time is the same as working everything out again
+    fn check(data: &mut Data) {
+        data.update_roads();
+        let kept = state(data);
+        for tile in data.tiles.values_mut() {
+            let mut counted = HashMap::new();
+            for x in 0..8 {
+                for y in 0..8 {
+                    if !tile.parts.contains_key(&Pos::new(x as i32, y as i32)) {
+                        *counted.entry(tile.land[x][y].building_type).or_insert(0) += 1;
+                    }
+                }
+            }
+            assert!(counted == tile.buildings, "the buildings on {} were miscounted", tile.pos.to_string());
+            tile.roads.clear();
+            tile.disconnected.clear();
+        }
+        data.income_current = false;
+        data.road_cells.clear();
+        data.road_hubs.clear();
+        data.connected_roads.clear();
+        data.update_roads();
+        let rebuilt = state(data);
+        for (pos, tile) in &kept.0 {
+            assert!(tile.0 == rebuilt.0[pos].0, "the buildings on {} differ", pos.to_string());
+            assert!(tile.1 == rebuilt.0[pos].1, "the income of {} differs", pos.to_string());
+            assert!(tile.2 == rebuilt.0[pos].2, "the roads on {} differ", pos.to_string());
+            assert!(tile.3 == rebuilt.0[pos].3, "the buildings cut off on {} differ", pos.to_string());
+        }
+        assert!(kept.0.len() == rebuilt.0.len());
+        assert!(kept.1 == rebuilt.1, "the income of the city differs");
+        assert!(kept.2 == rebuilt.2, "the connected roads differ");
+    }
+
+    #[test]
+    fn incremental_updates_match_a_rebuild() {
+        let mut data = Data::new("test".to_owned());
+        for r in Resource::iter() {
+            data.resources.add(r, 1_000_000);
+        }
+        let home = Pos::new(0, 0);
+        let next = Pos::new(1, 0);
+        data.tiles.insert(home, Tile::new(home));
+        check(&mut data);
+
+        // a town centre with a road to a fire station, and a fire station on a road of its own
+        build(&mut data, home, Pos::new(0, 0), BuildingType::Turret);
+        for y in 1..6 {
+            build(&mut data, home, Pos::new(0, y), BuildingType::Asphalt);
+        }
+        build(&mut data, home, Pos::new(1, 1), BuildingType::FireStation);
+        build(&mut data, home, Pos::new(5, 5), BuildingType::Asphalt);
+        build(&mut data, home, Pos::new(5, 6), BuildingType::FireStation);
+        check(&mut data);
+        assert!(data.disconnected_count() == 1);
+
+        // joining the roads up connects it
+        for x in 1..5 {
+            build(&mut data, home, Pos::new(x, 5), BuildingType::Asphalt);
+        }
+        check(&mut data);
+        assert!(data.disconnected_count() == 0);
+
+        // and removing a piece cuts it off again
+        build(&mut data, home, Pos::new(2, 5), BuildingType::Ground);
+        check(&mut data);
+        assert!(data.disconnected_count() == 1);
+
+        // replacing a building in place
+        build(&mut data, home, Pos::new(1, 1), BuildingType::PoliceStation);
+        check(&mut data);
+
+        // undoing the replacement and the removal
+        assert!(data.undo());
+        check(&mut data);
+        assert!(data.undo());
+        check(&mut data);
+        assert!(data.disconnected_count() == 0);
+
+        // a road that carries on to the land next door
+        data.tiles.insert(next, Tile::new(next));
+        data.update_new_pos();
+        for y in 6..8 {
+            build(&mut data, home, Pos::new(0, y), BuildingType::Asphalt);
+        }
+        build(&mut data, next, Pos::new(0, 0), BuildingType::Asphalt);
+        build(&mut data, next, Pos::new(1, 0), BuildingType::Hospital);
+        check(&mut data);
+        assert!(data.disconnected_count() == 0);
+
+        // cutting the road on the first tile cuts off the building on the second
+        build(&mut data, home, Pos::new(0, 6), BuildingType::Ground);
+        check(&mut data);
+        assert!(data.disconnected_count() == 1);
+
+        // clearing the land and selling it
+        build(&mut data, next, Pos::new(1, 0), BuildingType::Ground);
+        build(&mut data, next, Pos::new(0, 0), BuildingType::Ground);
+        check(&mut data);
+        assert!(data.sell_land(next).is_ok());
+        check(&mut data);
+        assert!(data.undo());
+        check(&mut data);
+    }
+}
@@ -0,0 +1,491 @@
+use std::{
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use savefile::SavefileError;
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::{Data, Stage, GLOBAL_VERSION};
+
+/// version of the `.meta` files. these are much smaller than a full save and change less often,
+/// so they are versioned separately from `GLOBAL_VERSION`
+const META_VERSION: u32 = 0;
+
+/// a small summary of a save file that is stored next to it, so that the save browser
+/// can list every city without deserializing every full `Data`
+#[derive(Savefile, Clone)]
+pub struct SaveMeta {
+    pub name: String,
+    /// the highest stage that has been unlocked
+    pub stage: i32,
+    pub tiles: i32,
+    /// total time spent in the city, in seconds
+    pub play_time: u64,
+}
+
+impl SaveMeta {
+    /// build the metadata for a loaded save
+    pub fn from_data(data: &Data) -> SaveMeta {
+        SaveMeta {
+            name: data.name.clone(),
+            stage: data
+                .stage
+                .iter()
+                .filter(|s| s.enabled)
+                .map(|s| s.num)
+                .max()
+                .unwrap_or(1),
+            tiles: data.tiles.len() as i32,
+            play_time: data.play_time,
+        }
+    }
+}
+
+/// the folder that all the save files are kept in
+pub fn saves_dir() -> PathBuf {
+    crate::settings::data_dir().join("saves")
+}
+
+/// the path of the save file for a city
+pub fn save_path(name: &str) -> PathBuf {
+    saves_dir().join(format!("game_instance_{}.bin", name))
+}
+
+/// the path of the metadata file for a city
+pub fn meta_path(name: &str) -> PathBuf {
+    saves_dir().join(format!("game_instance_{}.meta", name))
+}
+
+/// write the save and its metadata to disk
+pub fn save(data: &Data) -> Result<(), SavefileError> {
+    std::fs::create_dir_all(saves_dir())?;
+    savefile::save_file(save_path(&data.name), GLOBAL_VERSION, data)?;
+    savefile::save_file(meta_path(&data.name), META_VERSION, &SaveMeta::from_data(data))
+}
+
+/// load a save by the name of the city
+pub fn load(name: &str) -> Result<Data, SavefileError> {
+    let mut data = savefile::load_file::<Data, _>(save_path(name), GLOBAL_VERSION)?;
+
+    // if the stages have changed since the file was saved then reset them to the new ones
+    let mut reset = false;
+    for (i, s) in data.stage.iter().enumerate() {
+        if s.buildings.len() != Stage::new(i as i32).buildings.len() {
+            reset = true;
+            break;
+        }
+    }
+    if reset {
+        for i in 0..data.stage.len() {
+            data.stage[i] = Stage::new(i as i32 + 1);
+        }
+    }
+    Ok(data)
+}
+
+/// move saves from where older versions of the game kept them into the data directory.
+/// files are moved rather than copied so that this only ever happens once. a file that cannot be
+/// moved is left where it is and the rest are still moved, so this returns every error that happened
+pub fn migrate_legacy() -> Vec<String> {
+    let dir = saves_dir();
+    if let Err(e) = std::fs::create_dir_all(&dir) {
+        return vec![format!("could not create the saves folder: {}", e)];
+    }
+    let mut errors = vec![];
+
+    // the first versions of the game kept a single save in the working directory
+    let legacy_file = PathBuf::from("game_instance.bin");
+    if legacy_file.exists() {
+        let moved = savefile::load_file::<Data, _>(&legacy_file, GLOBAL_VERSION).and_then(|data| {
+            if !save_path(&data.name).exists() {
+                save(&data)?;
+            }
+            Ok(std::fs::rename(&legacy_file, "game_instance.bin.migrated")?)
+        });
+        if let Err(e) = moved {
+            errors.push(format!("could not move {}: {:?}", legacy_file.display(), e));
+        }
+    }
+
+    // later versions kept saves in a `saves` folder in the working directory
+    let legacy_dir = PathBuf::from("saves");
+    if !legacy_dir.is_dir() || legacy_dir.canonicalize().ok() == dir.canonicalize().ok() {
+        return errors;
+    }
+    let files = match std::fs::read_dir(&legacy_dir) {
+        Ok(files) => files,
+        Err(e) => {
+            errors.push(format!("could not read {}: {}", legacy_dir.display(), e));
+            return errors;
+        }
+    };
+    for f in files.flatten() {
+        // the old single save was copied in without the underscore in its name
+        let name = f
+            .file_name()
+            .to_string_lossy()
+            .replace("game_instanceOld World", "game_instance_Old World");
+        let to = dir.join(name);
+        if to.exists() {
+            continue;
+        }
+        if std::fs::rename(f.path(), &to).is_err() {
+            // renaming does not work across drives
+            if let Err(e) = std::fs::copy(f.path(), &to).and_then(|_| std::fs::remove_file(f.path())) {
+                errors.push(format!("could not move {}: {}", f.path().display(), e));
+            }
+        }
+    }
+    // this only removes the folder if everything was moved out of it
+    let _ = std::fs::remove_dir(&legacy_dir);
+    errors
+}
+
+/// read the metadata for a save. saves from before metadata existed are loaded once and
+/// have their metadata written, so that they do not need to be loaded again
+fn load_meta(name: &str) -> Option<SaveMeta> {
+    if let Ok(meta) = savefile::load_file::<SaveMeta, _>(meta_path(name), META_VERSION) {
+        return Some(meta);
+    }
+    let data = load(name).ok()?;
+    let meta = SaveMeta::from_data(&data);
+    savefile::save_file(meta_path(name), META_VERSION, &meta).ok()?;
+    Some(meta)
+}
+
+/// check that a name can be used as part of a file name
+pub fn valid_name(name: &str) -> bool {
+    !name.trim().is_empty()
+        && !name
+            .chars()
+            .any(|c| ['/', '\\', ':', '*', '?', '"', '<', '>', '|'].contains(&c))
+}
+
+/// rename a city. the name is stored inside the save as well as in the file name, so the save is rewritten
+pub fn rename(old: &str, new: &str) -> Result<(), SavefileError> {
+    if !valid_name(new) {
+        return Err(SavefileError::GeneralError {
+            msg: format!("\"{}\" is not a valid city name", new),
+        });
+    }
+    if save_path(new).exists() {
+        return Err(SavefileError::GeneralError {
+            msg: format!("a city called \"{}\" already exists", new),
+        });
+    }
+    let mut data = load(old)?;
+    data.name = new.to_owned();
+    save(&data)?;
+    delete(old)?;
+    Ok(())
+}
+
+/// copy a city into a new save. returns the name of the copy
+pub fn duplicate(name: &str) -> Result<String, SavefileError> {
+    let mut new_name = format!("{} copy", name);
+    let mut n = 2;
+    while save_path(&new_name).exists() {
+        new_name = format!("{} copy {}", name, n);
+        n += 1;
+    }
+    let mut data = load(name)?;
+    data.name = new_name.clone();
+    save(&data)?;
+    Ok(new_name)
+}
+
+/// remove a save and its metadata
+pub fn delete(name: &str) -> Result<(), SavefileError> {
+    std::fs::remove_file(save_path(name))?;
+    // older saves may not have a metadata file
+    let _ = std::fs::remove_file(meta_path(name));
+    Ok(())
+}
+
+/// format a number of seconds as hours and minutes
+pub fn format_duration(secs: u64) -> String {
+    match secs {
+        0..=59 => format!("{}s", secs),
+        60..=3599 => format!("{}m", secs / 60),
+        _ => format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60),
+    }
+}
+
+/// format a unix timestamp as how long ago it was
+fn format_ago(time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(time);
+    format!("{} ago", format_duration(now.saturating_sub(time)))
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, EnumIter)]
+pub enum SortBy {
+    Modified,
+    Name,
+    Stage,
+    Tiles,
+    PlayTime,
+}
+
+impl SortBy {
+    fn name(&self) -> &'static str {
+        match self {
+            SortBy::Modified => "Last Played",
+            SortBy::Name => "Name",
+            SortBy::Stage => "Stage",
+            SortBy::Tiles => "Tiles",
+            SortBy::PlayTime => "Play Time",
+        }
+    }
+}
+
+/// a save that is shown in the save browser
+pub struct SaveEntry {
+    pub name: String,
+    /// `None` if the save could not be read
+    pub meta: Option<SaveMeta>,
+    /// when the save file was last written, as a unix timestamp
+    pub modified: u64,
+}
+
+/// the list of saves on the main menu, with everything needed to rename, copy and delete them
+pub struct SaveBrowser {
+    entries: Vec<SaveEntry>,
+    search: String,
+    sort: SortBy,
+    reverse: bool,
+    /// the save that is being renamed, and the new name that is being typed
+    renaming: Option<(String, String)>,
+    /// the save that is waiting for the player to confirm that it should be deleted
+    confirm_delete: Option<String>,
+    /// set when the files on disk have changed and the list needs to be read again
+    dirty: bool,
+}
+
+impl Default for SaveBrowser {
+    fn default() -> Self {
+        SaveBrowser {
+            entries: vec![],
+            search: String::new(),
+            sort: SortBy::Modified,
+            reverse: false,
+            renaming: None,
+            confirm_delete: None,
+            dirty: true,
+        }
+    }
+}
+
+impl SaveBrowser {
+    /// read the list of saves from disk
+    fn refresh(&mut self) {
+        self.dirty = false;
+        self.entries.clear();
+        let files = match std::fs::read_dir(saves_dir()) {
+            Ok(f) => f,
+            Err(_) => return,
+        };
+        for f in files.flatten() {
+            let file_name = f.file_name().to_string_lossy().to_string();
+            let name = match file_name
+                .strip_prefix("game_instance_")
+                .and_then(|x| x.strip_suffix(".bin"))
+            {
+                Some(n) => n.to_owned(),
+                None => continue,
+            };
+            let modified = f
+                .metadata()
+                .and_then(|m| m.modified())
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            self.entries.push(SaveEntry {
+                meta: load_meta(&name),
+                name,
+                modified,
+            });
+        }
+        self.sort();
+    }
+
+    fn sort(&mut self) {
+        let key = |e: &SaveEntry| -> (i64, String) {
+            let meta = e.meta.as_ref();
+            let n = match self.sort {
+                SortBy::Name => 0,
+                SortBy::Modified => -(e.modified as i64),
+                SortBy::Stage => -meta.map(|m| m.stage as i64).unwrap_or(0),
+                SortBy::Tiles => -meta.map(|m| m.tiles as i64).unwrap_or(0),
+                SortBy::PlayTime => -meta.map(|m| m.play_time as i64).unwrap_or(0),
+            };
+            (n, e.name.to_lowercase())
+        };
+        self.entries.sort_by_key(key);
+        if self.reverse {
+            self.entries.reverse();
+        }
+    }
+
+    /// mark the list as out of date so that it is read again next frame
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// render the save browser. returns true if a save was loaded into `data`
+    pub fn render(&mut self, ui: &mut egui::Ui, data: &mut Data) -> bool {
+        if self.dirty {
+            self.refresh();
+        }
+        let mut loaded = false;
+
+        ui.heading("Saved Games");
+        ui.horizontal(|ui| {
+            ui.label(egui_phosphor::MAGNIFYING_GLASS);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.search)
+                    .hint_text("Search")
+                    .desired_width(120.0),
+            );
+        });
+        ui.horizontal(|ui| {
+            let old = (self.sort, self.reverse);
+            egui::ComboBox::from_id_source("save sort")
+                .selected_text(self.sort.name())
+                .show_ui(ui, |ui| {
+                    for s in SortBy::iter() {
+                        ui.selectable_value(&mut self.sort, s, s.name());
+                    }
+                });
+            let icon = match self.reverse {
+                true => egui_phosphor::SORT_ASCENDING,
+                false => egui_phosphor::SORT_DESCENDING,
+            };
+            ui.toggle_value(&mut self.reverse, icon);
+            if old != (self.sort, self.reverse) {
+                self.sort();
+            }
+            if ui.small_button(egui_phosphor::ARROWS_CLOCKWISE).on_hover_text("Refresh").clicked() {
+                self.dirty = true;
+            }
+        });
+        ui.separator();
+
+        let search = self.search.to_lowercase();
+        let mut changed = false;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for entry in &self.entries {
+                if !entry.name.to_lowercase().contains(&search) {
+                    continue;
+                }
+                ui.group(|ui| {
+                    ui.set_width(ui.available_width());
+                    match &mut self.renaming {
+                        Some((old, new)) if old == &entry.name => {
+                            ui.text_edit_singleline(new);
+                            ui.horizontal(|ui| {
+                                if ui.small_button("Rename").clicked() {
+                                    match rename(old, new) {
+                                        Ok(_) => changed = true,
+                                        Err(e) => data.error(e),
+                                    }
+                                }
+                                if ui.small_button("Cancel").clicked() {
+                                    changed = true;
+                                }
+                            });
+                        }
+                        _ => {
+                            ui.strong(&entry.name);
+                        }
+                    }
+                    match &entry.meta {
+                        Some(meta) => {
+                            ui.small(format!(
+                                "Stage {}  {} {}  {} {}",
+                                meta.stage,
+                                egui_phosphor::SQUARES_FOUR,
+                                meta.tiles,
+                                egui_phosphor::TIMER,
+                                format_duration(meta.play_time),
+                            ));
+                        }
+                        None => {
+                            ui.small("could not read save");
+                        }
+                    }
+                    ui.small(format!("last played {}", format_ago(entry.modified)));
+
+                    if self.confirm_delete.as_ref() == Some(&entry.name) {
+                        ui.horizontal(|ui| {
+                            ui.small(format!("Delete \"{}\"?", entry.name));
+                            if ui.small_button("Yes").clicked() {
+                                match delete(&entry.name) {
+                                    Ok(_) => data.info(format!("deleted {}", entry.name)),
+                                    Err(e) => data.error(e),
+                                }
+                                changed = true;
+                            }
+                            if ui.small_button("No").clicked() {
+                                self.confirm_delete = None;
+                            }
+                        });
+                        return;
+                    }
+
+                    ui.horizontal(|ui| {
+                        if ui.small_button(format!("{} Load", egui_phosphor::PLAY)).clicked() {
+                            match load(&entry.name) {
+                                Ok(load) => {
+                                    *data = load;
+                                    loaded = true;
+                                }
+                                Err(e) => data.error(e),
+                            }
+                        }
+                        if ui.small_button(egui_phosphor::PENCIL_SIMPLE).on_hover_text("Rename").clicked() {
+                            self.renaming = Some((entry.name.clone(), entry.name.clone()));
+                        }
+                        if ui.small_button(egui_phosphor::COPY).on_hover_text("Duplicate").clicked() {
+                            if let Err(e) = duplicate(&entry.name) {
+                                data.error(e);
+                            }
+                            changed = true;
+                        }
+                        if ui.small_button(egui_phosphor::TRASH).on_hover_text("Delete").clicked() {
+                            self.confirm_delete = Some(entry.name.clone());
+                        }
+                    });
+                });
+            }
+        });
+        if changed {
+            self.renaming = None;
+            self.confirm_delete = None;
+            self.dirty = true;
+        }
+        loaded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the saves in the `saves` folder were made by version 6 of the game, before any of the buildings
+    /// or fields that were added since. renaming a building that they hold would stop them loading
+    #[test]
+    fn loads_saves_from_older_versions() {
+        let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("saves");
+        for file in std::fs::read_dir(dir).unwrap().flatten().map(|f| f.path()) {
+            if let Err(e) = savefile::load_file::<Data, _>(&file, GLOBAL_VERSION) {
+                panic!("{} did not load: {:?}", file.display(), e);
+            }
+        }
+    }
+}
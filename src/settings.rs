@@ -0,0 +1,146 @@
+use std::{path::PathBuf, sync::OnceLock};
+
+use savefile::SavefileError;
+
+use crate::keys::Bindings;
+
+/// version of the settings file. this is separate from `GLOBAL_VERSION` because settings are not part of a city
+const SETTINGS_VERSION: u32 = 2;
+
+/// the name of the folder that the game keeps its files in
+const APP_NAME: &str = "mini_city";
+
+/// environment variable that can be used to move the data directory
+const DATA_DIR_ENV: &str = "MINI_CITY_DATA_DIR";
+
+/// command line flag that can be used to move the data directory. takes priority over the environment variable
+const DATA_DIR_FLAG: &str = "--data-dir";
+
+/// the folder that saves and settings are stored in. this is worked out once at startup from, in order:
+/// the `--data-dir` flag, the `MINI_CITY_DATA_DIR` environment variable, and then the normal place for the platform
+pub fn data_dir() -> &'static PathBuf {
+    static DIR: OnceLock<PathBuf> = OnceLock::new();
+    DIR.get_or_init(|| {
+        let mut args = std::env::args();
+        while let Some(arg) = args.next() {
+            if arg == DATA_DIR_FLAG {
+                if let Some(dir) = args.next() {
+                    return PathBuf::from(dir);
+                }
+            } else if let Some(dir) = arg.strip_prefix("--data-dir=") {
+                return PathBuf::from(dir);
+            }
+        }
+        if let Some(dir) = std::env::var_os(DATA_DIR_ENV) {
+            return PathBuf::from(dir);
+        }
+        platform_data_dir()
+            .map(|x| x.join(APP_NAME))
+            .unwrap_or_else(|| PathBuf::from("."))
+    })
+}
+
+/// the folder that the platform expects programs to keep their data in
+fn platform_data_dir() -> Option<PathBuf> {
+    let env = |x: &str| std::env::var_os(x).filter(|x| !x.is_empty()).map(PathBuf::from);
+    if cfg!(target_os = "windows") {
+        env("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env("HOME").map(|x| x.join("Library").join("Application Support"))
+    } else {
+        env("XDG_DATA_HOME").or_else(|| env("HOME").map(|x| x.join(".local").join("share")))
+    }
+}
+
+fn settings_path() -> PathBuf {
+    data_dir().join("settings.bin")
+}
+
+/// settings that apply to the whole game rather than to a single city
+#[derive(Savefile)]
+pub struct Settings {
+    pub ui_scale: f32,
+    pub dark_mode: bool,
+    /// how often the current city is saved, in seconds
+    pub autosave_secs: u64,
+    /// the keys bound to each action
+    #[savefile_versions = "1.."]
+    pub keys: Bindings,
+
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub open: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            ui_scale: 1.3,
+            dark_mode: false,
+            autosave_secs: 3,
+            keys: Bindings::default(),
+            open: false,
+        }
+    }
+}
+
+impl Settings {
+    /// load the settings file, or the default settings if there isn't one
+    pub fn load() -> Settings {
+        let mut settings: Settings = savefile::load_file(settings_path(), SETTINGS_VERSION).unwrap_or_default();
+        settings.keys.upgrade();
+        settings
+    }
+
+    pub fn save(&self) -> Result<(), SavefileError> {
+        std::fs::create_dir_all(data_dir())?;
+        savefile::save_file(settings_path(), SETTINGS_VERSION, self)
+    }
+
+    /// set the egui style to match the settings
+    pub fn apply(&self, egui_ctx: &egui::Context) {
+        egui_ctx.set_pixels_per_point(self.ui_scale);
+        egui_ctx.set_visuals(match self.dark_mode {
+            true => egui::Visuals::dark(),
+            false => egui::Visuals::light(),
+        });
+    }
+
+    /// render the settings window. returns true if a setting was changed
+    pub fn render(&mut self, egui_ctx: &egui::Context) -> bool {
+        let mut changed = false;
+        let mut open = self.open;
+        egui::Window::new(format!("{} Settings", egui_phosphor::GEAR))
+            .open(&mut open)
+            .resizable(false)
+            .show(egui_ctx, |ui| {
+                egui::Grid::new("settings grid").show(ui, |ui| {
+                    ui.label("UI Scale");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut self.ui_scale, 0.5..=3.0))
+                        .drag_released();
+                    ui.end_row();
+
+                    ui.label("Theme");
+                    ui.horizontal(|ui| {
+                        changed |= ui.selectable_value(&mut self.dark_mode, false, "Light").clicked();
+                        changed |= ui.selectable_value(&mut self.dark_mode, true, "Dark").clicked();
+                    });
+                    ui.end_row();
+
+                    ui.label("Autosave every");
+                    changed |= ui
+                        .add(egui::Slider::new(&mut self.autosave_secs, 3..=300).suffix("s"))
+                        .changed();
+                    ui.end_row();
+                });
+                egui::CollapsingHeader::new(format!("{} Keys", egui_phosphor::KEYBOARD)).show(ui, |ui| {
+                    changed |= self.keys.render(ui);
+                });
+                ui.separator();
+                ui.small(format!("game files are stored in {}", data_dir().display()));
+            });
+        self.open = open;
+        changed
+    }
+}
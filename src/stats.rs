@@ -0,0 +1,285 @@
+use std::collections::HashSet;
+
+use egui::plot::{Legend, Line, Plot, PlotPoints};
+use strum::IntoEnumIterator;
+
+use crate::{
+    building::{BuildingType, Resource},
+    resources::{ResourceBag, ResourceMap},
+    saves::format_duration,
+    Data, TICK_SECS,
+};
+
+/// the number of ticks that are kept, which is an hour of play
+const HISTORY_TICKS: usize = 1200;
+
+/// the time windows that the statistics can be shown over, in ticks
+const WINDOWS: [(usize, &str); 3] = [(20, "1 minute"), (200, "10 minutes"), (HISTORY_TICKS, "1 hour")];
+
+/// the economy at the end of a tick
+#[derive(Savefile, Clone, Default)]
+pub struct Snapshot {
+    /// how much of each resource there was, counting every stockpile
+    #[savefile_versions_as = "0..15:ResourceMap"]
+    #[savefile_versions = "16.."]
+    pub resources: ResourceBag,
+    /// how much of each resource was made during the tick
+    #[savefile_versions_as = "0..15:ResourceMap"]
+    #[savefile_versions = "16.."]
+    pub produced: ResourceBag,
+    /// how much of each resource was spent, or lost because storage was full, since the tick before
+    #[savefile_versions_as = "0..15:ResourceMap"]
+    #[savefile_versions = "16.."]
+    pub consumed: ResourceBag,
+}
+
+/// the economy over the last hour of play, kept as a ring buffer of snapshots
+#[derive(Savefile, Default)]
+pub struct Stats {
+    ring: Vec<Snapshot>,
+    /// where the next snapshot goes once the ring is full
+    next: usize,
+    #[savefile_ignore]
+    pub open: bool,
+    /// the number of ticks shown in the statistics window
+    #[savefile_ignore]
+    window: usize,
+    /// the resources drawn on the chart
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    charted: HashSet<Resource>,
+}
+
+impl Stats {
+    fn push(&mut self, snapshot: Snapshot) {
+        if self.ring.len() < HISTORY_TICKS {
+            self.ring.push(snapshot);
+        } else {
+            self.ring[self.next] = snapshot;
+        }
+        self.next = (self.next + 1) % HISTORY_TICKS;
+    }
+
+    /// the last `n` snapshots, oldest first
+    pub fn recent(&self, n: usize) -> Vec<&Snapshot> {
+        let ordered = self.ring[self.next.min(self.ring.len())..]
+            .iter()
+            .chain(self.ring[..self.next.min(self.ring.len())].iter())
+            .collect::<Vec<_>>();
+        ordered[ordered.len().saturating_sub(n)..].to_vec()
+    }
+
+    /// the most recent snapshot
+    pub fn last(&self) -> Option<&Snapshot> {
+        self.recent(1).pop()
+    }
+}
+
+/// the average of a resource over some snapshots
+fn average(snapshots: &[&Snapshot], f: impl Fn(&Snapshot) -> i64) -> f32 {
+    match snapshots.len() {
+        0 => 0.0,
+        n => snapshots.iter().map(|x| f(x)).sum::<i64>() as f32 / n as f32,
+    }
+}
+
+impl Data {
+    /// record the economy at the end of a tick
+    pub fn record_stats(&mut self) {
+        let resources = self.totals();
+        let produced = self.per_tick;
+        let consumed = match self.stats.last() {
+            Some(last) => (produced - (resources - last.resources)).iter().filter(|x| x.1 > 0).collect(),
+            None => ResourceBag::default(),
+        };
+        self.stats.push(Snapshot {
+            resources,
+            produced,
+            consumed,
+        });
+    }
+
+    /// the number of working buildings of each type that make something, most common first
+    fn production_by_building(&self) -> Vec<(BuildingType, i32)> {
+        let mut production = self
+            .income
+            .working
+            .iter()
+            .filter(|x| *x.1 > 0 && !x.0.output().is_empty())
+            .map(|x| (*x.0, *x.1))
+            .collect::<Vec<_>>();
+        production.sort_by_key(|x| (-x.1, x.0.name()));
+        production
+    }
+
+    /// render the statistics window
+    pub fn render_stats(&mut self, egui_ctx: &egui::Context) {
+        if !self.stats.open {
+            return;
+        }
+        if self.stats.window == 0 {
+            self.stats.window = WINDOWS[0].0;
+        }
+        let (storage, cash_storage) = self.storage_caps();
+        let production = self.production_by_building();
+        let mut open = self.stats.open;
+        let mut window = self.stats.window;
+        let mut charted = self.stats.charted.clone();
+        egui::Window::new(format!("{} Statistics", egui_phosphor::CHART_LINE))
+            .open(&mut open)
+            .vscroll(true)
+            .show(egui_ctx, |ui| {
+                let snapshots = self.stats.recent(window);
+                ui.horizontal(|ui| {
+                    for (ticks, name) in WINDOWS {
+                        ui.selectable_value(&mut window, ticks, name);
+                    }
+                });
+                if snapshots.len() < 2 {
+                    ui.label("there is not enough history yet. statistics are recorded every tick");
+                    return;
+                }
+                let first = snapshots[0];
+                let last = snapshots[snapshots.len() - 1];
+                let ticks = (snapshots.len() - 1) as f32;
+
+                egui::Grid::new("statistics").striped(true).show(ui, |ui| {
+                    ui.label("");
+                    ui.label("now");
+                    ui.label("made");
+                    ui.label("spent");
+                    ui.label("full in");
+                    ui.end_row();
+                    for r in Resource::iter() {
+                        let now = last.resources.get(r);
+                        let made = average(&snapshots, |x| x.produced.get(r));
+                        let spent = average(&snapshots, |x| x.consumed.get(r));
+                        if now == 0 && made == 0.0 && spent == 0.0 {
+                            continue;
+                        }
+                        let cap = match r {
+                            Resource::Tax | Resource::CashStorage => storage + cash_storage,
+                            _ => storage,
+                        };
+                        let rate = (now - first.resources.get(r)) as f32 / ticks;
+                        let full_in = match (now >= cap, rate > 0.0) {
+                            (true, _) => "full".to_owned(),
+                            (false, true) => {
+                                format_duration(((cap - now) as f32 / rate).ceil() as u64 * TICK_SECS)
+                            }
+                            (false, false) => "never".to_owned(),
+                        };
+                        let mut shown = charted.contains(&r);
+                        if ui.checkbox(&mut shown, format!("{} {}", r.symbol(), r.name())).changed() {
+                            match shown {
+                                true => charted.insert(r),
+                                false => charted.remove(&r),
+                            };
+                        }
+                        ui.label(format!("{}", now));
+                        ui.label(format!("{:.1}", made));
+                        ui.label(format!("{:.1}", spent));
+                        ui.label(full_in);
+                        ui.end_row();
+                    }
+                });
+                ui.small("made and spent are per tick, averaged over the time shown. spent includes what was lost because storage was full");
+
+                if !charted.is_empty() {
+                    Plot::new("statistics chart")
+                        .height(160.0)
+                        .legend(Legend::default())
+                        .allow_drag(false)
+                        .allow_zoom(false)
+                        .allow_scroll(false)
+                        .include_y(0.0)
+                        .show(ui, |plot| {
+                            for r in Resource::iter().filter(|r| charted.contains(r)) {
+                                // the x axis is in minutes, ending now
+                                let points = snapshots
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(i, x)| {
+                                        let ago = (snapshots.len() - 1 - i) as f64 * TICK_SECS as f64 / 60.0;
+                                        [-ago, x.resources.get(r) as f64]
+                                    })
+                                    .collect::<Vec<_>>();
+                                plot.line(Line::new(PlotPoints::from(points)).name(r.name()));
+                            }
+                        });
+                } else {
+                    ui.small("tick a resource to chart it");
+                }
+
+                ui.collapsing("production by building", |ui| {
+                    egui::Grid::new("production by building").striped(true).show(ui, |ui| {
+                        for (b, n) in &production {
+                            ui.label(format!("{} {} x{}", b.symbol(), b.name(), n));
+                            ui.label(
+                                b.output()
+                                    .iter()
+                                    .map(|x| format!("{}{} ", x.0.symbol(), x.1 * n))
+                                    .collect::<String>(),
+                            );
+                            ui.end_row();
+                        }
+                    });
+                });
+            });
+        self.stats.open = open;
+        self.stats.window = window;
+        self.stats.charted = charted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// a snapshot that is told apart by how much food there was
+    fn snapshot(food: i64) -> Snapshot {
+        Snapshot {
+            resources: [(Resource::Food, food)].into_iter().collect(),
+            ..Snapshot::default()
+        }
+    }
+
+    fn foods(snapshots: &[&Snapshot]) -> Vec<i64> {
+        snapshots.iter().map(|x| x.resources.get(Resource::Food)).collect()
+    }
+
+    fn stats_with(ticks: i64) -> Stats {
+        let mut stats = Stats::default();
+        for i in 0..ticks {
+            stats.push(snapshot(i));
+        }
+        stats
+    }
+
+    #[test]
+    fn recent_before_the_ring_is_full() {
+        let stats = stats_with(5);
+        assert_eq!(foods(&stats.recent(3)), vec![2, 3, 4]);
+        assert_eq!(foods(&stats.recent(10)), vec![0, 1, 2, 3, 4]);
+        assert_eq!(foods(&stats.last().into_iter().collect::<Vec<_>>()), vec![4]);
+        assert!(Stats::default().recent(3).is_empty());
+    }
+
+    #[test]
+    fn recent_when_the_ring_is_exactly_full() {
+        let stats = stats_with(HISTORY_TICKS as i64);
+        assert_eq!(stats.next, 0);
+        assert_eq!(foods(&stats.recent(2)), vec![HISTORY_TICKS as i64 - 2, HISTORY_TICKS as i64 - 1]);
+    }
+
+    #[test]
+    fn recent_after_wrapping_around() {
+        let total = HISTORY_TICKS as i64 + 3;
+        let stats = stats_with(total);
+        assert_eq!(stats.ring.len(), HISTORY_TICKS);
+        // the newest snapshots are at the start of the ring, and the order runs across the join
+        assert_eq!(foods(&stats.recent(5)), (total - 5..total).collect::<Vec<_>>());
+        let all = stats.recent(HISTORY_TICKS * 2);
+        assert_eq!(foods(&all), (3..total).collect::<Vec<_>>());
+    }
+}
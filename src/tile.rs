@@ -4,11 +4,67 @@ use std::collections::HashMap;
 
 use crate::{
     building::{Building, BuildingType, Resource},
+    history::{Action, History},
+    income::Income,
+    resources::{ResourceBag, ResourceMap},
     EditTool, SelectTool,
 };
 
 use std::time::Instant;
 
+/// the size of a land tile on the screen when it is not zoomed
+pub const TILE_SIZE: f32 = 202.0;
+/// the size of a single cell of a land tile on the screen when it is not zoomed
+pub const CELL_SIZE: f32 = 25.0;
+/// how far the land can be zoomed out and in
+pub const MIN_ZOOM: f32 = 0.2;
+pub const MAX_ZOOM: f32 = 2.5;
+/// below this zoom tiles are drawn as a simple summary instead of a grid of buttons
+const SUMMARY_ZOOM: f32 = 0.5;
+/// the colours the land is drawn in. they follow the theme picked in the settings
+#[derive(Clone, Copy)]
+pub struct Palette {
+    /// the space around the land
+    pub background: Color32,
+    /// the ground on owned land
+    pub land: Color32,
+    /// the outline of each cell
+    pub cell_stroke: Color32,
+    /// a cell whose building breaks the placement rules
+    pub invalid: Color32,
+}
+
+impl Palette {
+    pub fn of(dark_mode: bool) -> Palette {
+        match dark_mode {
+            false => Palette {
+                background: Color32::WHITE,
+                land: Color32::from_rgb(215, 235, 210),
+                cell_stroke: Color32::from_rgb(200, 235, 200),
+                invalid: Color32::from_rgb(235, 215, 210),
+            },
+            true => Palette {
+                background: Color32::from_rgb(30, 30, 30),
+                land: Color32::from_rgb(45, 60, 45),
+                cell_stroke: Color32::from_rgb(60, 85, 60),
+                invalid: Color32::from_rgb(85, 50, 50),
+            },
+        }
+    }
+}
+
+/// turn an egui colour into a macroquad one
+pub fn to_macroquad(c: Color32) -> macroquad::color::Color {
+    macroquad::color::Color::from_rgba(c.r(), c.g(), c.b(), c.a())
+}
+/// the colour used to mark buildings that are not connected to the road network
+pub const DISCONNECTED_COLOR: egui::Color32 = egui::Color32::from_rgb(220, 60, 60);
+/// the outline drawn around the cell shown in the inspector
+const INSPECTED_STROKE: egui::Stroke = egui::Stroke {
+    width: 2.0,
+    color: egui::Color32::from_rgb(230, 170, 40),
+};
+
 #[derive(PartialEq, Eq, Hash, Copy, Clone, Savefile)]
 pub struct Pos {
     pub x: i32,
@@ -22,9 +78,54 @@ impl Default for Pos {
     }
 }
 
+/// where the land is drawn on the screen and how far it is zoomed
+#[derive(Clone, Copy)]
+pub struct View {
+    /// the screen position of the top left corner of the tile at `(0,0)`
+    pub offset: (f32, f32),
+    /// how far the land is zoomed in. 1 is the normal size
+    pub scale: f32,
+}
 
+impl View {
+    pub fn tile_size(&self) -> f32 {
+        TILE_SIZE * self.scale
+    }
 
-#[derive(Savefile)]
+    pub fn cell_size(&self) -> f32 {
+        CELL_SIZE * self.scale
+    }
+
+    /// the screen rect of a tile
+    pub fn tile_rect(&self, pos: Pos) -> Rect {
+        Rect::from_min_size(
+            Pos2::new(
+                self.offset.0 + pos.x as f32 * self.tile_size(),
+                self.offset.1 + pos.y as f32 * self.tile_size(),
+            ),
+            egui::Vec2::splat(self.tile_size()),
+        )
+    }
+
+    /// a point on the screen in tiles, so that `(1.5, 0.5)` is the middle of the tile at `(1,0)`
+    pub fn to_world(self, pos: Pos2) -> egui::Vec2 {
+        egui::Vec2::new(pos.x - self.offset.0, pos.y - self.offset.1) / self.tile_size()
+    }
+
+    /// a point in tiles on the screen. the reverse of `to_world`
+    pub fn to_screen(self, pos: egui::Vec2) -> Pos2 {
+        Pos2::new(self.offset.0, self.offset.1) + pos * self.tile_size()
+    }
+
+    /// check if tiles are zoomed out far enough to be drawn as a summary
+    pub fn summary(&self) -> bool {
+        self.scale < SUMMARY_ZOOM
+    }
+}
+
+
+
+#[derive(Savefile, Clone)]
 
 pub struct Tile {
     pub pos: Pos,
@@ -35,115 +136,419 @@ pub struct Tile {
     pub summary: bool,
     #[savefile_versions = "3.."]
     pub planned: HashMap<Pos, BuildingType>,
+    /// the cells covered by buildings that are bigger than one cell, each pointing to the anchor cell that the
+    /// building was placed on. covered cells hold a copy of the building so that neighbours see it along its whole perimeter
+    #[savefile_versions = "10.."]
+    pub parts: HashMap<Pos, Pos>,
+    /// the goods held on this tile when logistics is turned on
+    #[savefile_versions_as = "11..15:ResourceMap"]
+    #[savefile_versions = "16.."]
+    pub stock: ResourceBag,
+    /// the asphalt on the tile, and whether it leads back to a road hub. set by `Data::update_roads`
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub roads: HashMap<Pos, bool>,
+    /// the anchors of buildings that need a road but are not next to a connected one. they produce nothing
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub disconnected: HashMap<Pos, BuildingType>,
+    /// what the working buildings on the tile make, as last added to the city's income. set by `Data::refresh_income`
+    #[savefile_ignore]
+    #[savefile_introspect_ignore]
+    pub income: Income,
+}
+
+/// check if a cell is on the 8x8 grid of a tile
+fn in_bounds(i: Pos) -> bool {
+    i.x >= 0 && i.x < 8 && i.y >= 0 && i.y < 8
+}
+
+/// what happened when a tile was rendered
+#[derive(Default)]
+pub struct TileResponse {
+    /// the buildings or plans on the tile were changed
+    pub changed: bool,
+    /// the world cell that the pointer is over
+    pub hovered: Option<Pos>,
+    /// the world cell that was clicked with a tool that is handled by `Data` rather than the tile
+    pub clicked: Option<Pos>,
+    /// the player asked to copy the whole tile into a blueprint
+    pub copy_tile: bool,
+    /// the player clicked to build something that could not be built
+    pub failed: Option<Invalid>,
+}
+
+/// why a building cannot be put on a cell
+#[derive(Clone, PartialEq)]
+pub enum Invalid {
+    /// it needs these buildings next to it
+    MissingNeighbour(Vec<BuildingType>),
+    /// these neighbouring cells have buildings that are not allowed next to it
+    ForbiddenNeighbour(Vec<(Pos, BuildingType)>),
+    /// it needs these buildings somewhere on the same tile
+    MissingOnTile(Vec<BuildingType>),
+    /// there is not enough of these resources, along with how many more are needed
+    Unaffordable(ResourceBag),
+    /// replacing the building would leave the buildings on these cells invalid
+    BreaksOthers(Vec<(Pos, BuildingType)>),
+    /// the building would go over the edge of the tile
+    OutOfBounds,
+    /// the building would cover these cells, which already have buildings on them
+    Occupied(Vec<(Pos, BuildingType)>),
+}
+
+impl Invalid {
+    /// explain the reason to the player
+    pub fn describe(&self) -> String {
+        let names = |b: &mut dyn Iterator<Item = &BuildingType>| {
+            let mut names: Vec<String> = vec![];
+            for b in b {
+                let name = format!("{} {}", b.symbol(), b.name());
+                if !names.contains(&name) {
+                    names.push(name);
+                }
+            }
+            names.join(", ")
+        };
+        match self {
+            Invalid::MissingNeighbour(b) => format!("needs to be next to {}", names(&mut b.iter())),
+            Invalid::ForbiddenNeighbour(b) => format!("cannot be next to {}", names(&mut b.iter().map(|x| &x.1))),
+            Invalid::MissingOnTile(b) => format!("needs {} somewhere on this tile", names(&mut b.iter())),
+            Invalid::OutOfBounds => "does not fit on the tile".to_owned(),
+            Invalid::Occupied(b) => format!("the space is taken by {}", names(&mut b.iter().map(|x| &x.1))),
+            Invalid::BreaksOthers(b) => format!("would leave {} without what it needs", names(&mut b.iter().map(|x| &x.1))),
+            Invalid::Unaffordable(r) => format!(
+                "needs {}more",
+                r.iter().map(|x| format!("{}{} ", x.0.symbol(), x.1)).collect::<String>()
+            ),
+        }
+    }
+
+    /// the cells on the tile that are causing the problem
+    pub fn cells(&self) -> Vec<Pos> {
+        match self {
+            Invalid::ForbiddenNeighbour(b) | Invalid::BreaksOthers(b) | Invalid::Occupied(b) => {
+                b.iter().map(|x| x.0).collect()
+            }
+            _ => vec![],
+        }
+    }
+}
+
+/// shown in toasts as the explanation on its own
+impl std::fmt::Debug for Invalid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.describe())
+    }
+}
+
+/// a copy of the buildings and plans on a tile, used to undo changes to it
+#[derive(Clone, PartialEq)]
+pub struct TileState {
+    land: [[BuildingType; 8]; 8],
+    planned: HashMap<Pos, BuildingType>,
+    parts: HashMap<Pos, Pos>,
 }
 
 impl Tile {
     /// create a new blank land tile at the specified position
     pub fn new(coord: Pos) -> Tile {
         let land: [[Building; 8]; 8] = Default::default();
-        Tile {
+        let mut tile = Tile {
             planned: HashMap::new(),
+            parts: HashMap::new(),
+            stock: ResourceBag::default(),
+            roads: HashMap::new(),
+            disconnected: HashMap::new(),
             summary: false,
             pos: coord,
             land,
             buildings: HashMap::new(),
             neighbors_buildings: HashMap::new(),
-        }
+            income: Income::default(),
+        };
+        tile.count_buildings();
+        tile
     }
-    /// calculate the max amount of resources that the player is able to store, with a different number for cash storage.  
-    pub fn process_storage(&self, mut storage: i32, mut cash_storage: i32) -> (i32, i32) {
-        for i in &self.buildings {
-            for n in i.0.output() {
-                match n.0 {
-                    Resource::Storage => storage += n.1 * i.1,
-                    Resource::CashStorage => cash_storage += n.1 * i.1,
-                    _ => {}
-                }
+    /// how many of each building on the tile are working, leaving out ones that are not connected to the road network
+    pub fn working(&self) -> HashMap<BuildingType, i32> {
+        let mut working = self.buildings.clone();
+        for b in self.disconnected.values() {
+            if let Some(n) = working.get_mut(b) {
+                *n -= 1;
             }
         }
-        return (storage, cash_storage);
-    }
-
-    /// calculate the new number of resources that the player has each game tick (every 3 to 4 seconds)
-    pub fn processes_resources(
-        &self,
-        res: &mut HashMap<Resource, i32>,
-        storage: i32,
-        cash_storage: i32,
-        per_sec: &mut HashMap<Resource, i32>,
-    ) {
-        for i in &self.buildings {
-            for n in i.0.output() {
-                per_sec.insert(n.0, per_sec.get(&n.0).unwrap_or(&0) + n.1 * i.1);
-                res.insert(
-                    n.0,
-                    (res.get(&n.0).unwrap_or(&0) + n.1 * i.1).min(match n.0 {
-                        Resource::CashStorage => cash_storage + storage,
-                        Resource::Tax => cash_storage+storage,
-                        _ => storage,
-                    }),
-                );
+        working
+    }
+
+    /// the cells that a building would cover with its anchor on a cell, including any that are off the tile
+    pub fn footprint(anchor: Pos, building_type: &BuildingType) -> Vec<Pos> {
+        let (rows, cols) = building_type.footprint();
+        (0..rows)
+            .flat_map(|r| (0..cols).map(move |c| Pos::new(anchor.x + r, anchor.y + c)))
+            .collect()
+    }
+
+    /// the anchor cell of the building that covers a cell
+    pub fn anchor(&self, i: Pos) -> Pos {
+        *self.parts.get(&i).unwrap_or(&i)
+    }
+
+    /// take the building that covers a cell off every cell that it covers, and return it
+    fn clear_footprint(&mut self, i: Pos) -> Building {
+        let anchor = self.anchor(i);
+        let building = std::mem::take(&mut self.land[anchor.x as usize][anchor.y as usize]);
+        let mut cleared = 0;
+        for cell in Tile::footprint(anchor, &building.building_type) {
+            if in_bounds(cell) && self.anchor(cell) == anchor {
+                self.land[cell.x as usize][cell.y as usize] = Building::new(&BuildingType::Ground);
+                self.parts.remove(&cell);
+                cleared += 1;
             }
-            
         }
+        self.add_count(building.building_type, -1);
+        self.add_count(BuildingType::Ground, cleared);
+        building
     }
 
-
-    /// calculate the new number of resources that the player has each game tick
-    fn update_count(&mut self, resources: &mut HashMap<Resource, i32>) {
-        self.buildings.clear();
-        for i in self.land.iter() {
-            for j in i.iter() {
-                
-                self.buildings.insert(
-                    j.building_type,
-                    self.buildings.get(&j.building_type).unwrap_or(&0) + 1,
-                );
+    /// the cells around the edge of a building with its anchor on a cell, along with what is on them
+    pub fn neighbours(&self, anchor: Pos, building_type: &BuildingType) -> Vec<(Pos, BuildingType)> {
+        let cells = Tile::footprint(anchor, building_type);
+        let mut neighbours: Vec<(Pos, BuildingType)> = vec![];
+        for cell in &cells {
+            for n in &cell.get_adjacent()[0..4] {
+                if in_bounds(*n) && !cells.contains(n) && !neighbours.iter().any(|x| &x.0 == n) {
+                    neighbours.push((*n, self.land[n.x as usize][n.y as usize].building_type));
+                }
             }
         }
+        neighbours
+    }
 
+    /// count how many of each building there are on the tile
+    fn count_buildings(&mut self) {
+        self.buildings.clear();
         for x in 0..8 {
             for y in 0..8 {
-                if !self.is_valid(Pos::new(x as i32, y as i32), &self.land[x][y]) {
-
-                    self.planned.insert(Pos::new(x as i32, y as i32), self.land[x][y].building_type);
-                    for c in &self.land[x][y].cost {
-                        resources.insert(c.0, resources.get(&c.0).unwrap_or(&0) + c.1);
-                    }
-                    self.land[x][y] = Building::new(&BuildingType::Ground);
+                // buildings that cover more than one cell are only counted on their anchor
+                if self.parts.contains_key(&Pos::new(x as i32, y as i32)) {
+                    continue;
                 }
+                self.add_count(self.land[x][y].building_type, 1);
             }
         }
+    }
 
-        
+    /// change how many of a building there are on the tile, so that a placement does not need to count every cell
+    fn add_count(&mut self, building_type: BuildingType, n: i32) {
+        let count = self.buildings.entry(building_type).or_insert(0);
+        *count += n;
+        if *count <= 0 {
+            self.buildings.remove(&building_type);
+        }
     }
-    /// check if a building is valid at a certain position
-    pub fn is_valid(&self, i: Pos, new_building: &Building) -> bool {
+
+    /// the cells that could have become invalid after a building with its anchor on a cell changed. the
+    /// neighbours of the building can break its adjacency rules, and if `removed` was the last building of
+    /// its type then anything on the tile that needs one could be broken
+    fn affected(&self, anchor: Pos, building_type: &BuildingType, removed: BuildingType) -> Vec<Pos> {
+        match removed != BuildingType::Ground && !self.buildings.contains_key(&removed) {
+            true => (0..64).map(|x| Pos::new(x / 8, x % 8)).collect(),
+            false => self.neighbours(anchor, building_type).into_iter().map(|x| x.0).collect(),
+        }
+    }
+
+    /// take away the buildings on `cells` that are no longer valid and plan them again, refunding what they cost.
+    /// taking a building away can break the buildings around it, so they are checked in turn
+    fn revalidate(&mut self, mut cells: Vec<Pos>, resources: &mut ResourceBag) {
+        while let Some(cell) = cells.pop() {
+            let cell = self.anchor(cell);
+            let building_type = self.land[cell.x as usize][cell.y as usize].building_type;
+            if self.is_valid(cell, &self.land[cell.x as usize][cell.y as usize]).is_ok() {
+                continue;
+            }
+            self.planned.insert(cell, building_type);
+            *resources += ResourceBag::of(&self.clear_footprint(cell).cost);
+            cells.extend(self.affected(cell, &building_type, building_type));
+        }
+    }
+
+    /// count the buildings and check every cell from scratch, such as after the whole tile was replaced
+    fn update_count(&mut self, resources: &mut ResourceBag) {
+        self.count_buildings();
+        self.revalidate((0..64).map(|x| Pos::new(x / 8, x % 8)).collect(), resources);
+    }
+
+    /// try to put a building on a cell, paying for it, or refunding the building that was there if the new one is ground.
+    /// buildings that cover more than one cell are placed with their anchor on the cell, and removed all at once.
+    /// returns true if the land was changed
+    pub fn place(&mut self, i: Pos, new_building: Building, resources: &mut ResourceBag) -> bool {
+        let current = self.land[i.x as usize][i.y as usize].clone();
+        if current.building_type == new_building.building_type {
+            self.planned.remove(&i);
+            return false;
+        }
+
         if new_building.building_type == BuildingType::Ground {
+            let anchor = self.anchor(i);
+            let storage = resources.get(Resource::Storage);
+            let removed = self.clear_footprint(i);
+            resources.refund(&ResourceBag::of(&removed.cost), storage);
+
+            let affected = self.affected(anchor, &removed.building_type, removed.building_type);
+            self.revalidate(affected, resources);
             return true;
         }
-        for requirement in &new_building.tile_adj {
-            if let Some(_) = self.buildings.get(requirement) {
-            } else {
-                return false;
+
+        if self.can_place(i, &new_building, resources).is_err() {
+            return false;
+        }
+
+        self.planned.remove(&i);
+
+        // replacing a building only costs the difference. refunds are capped by storage like they are when removing
+        let storage = resources.get(Resource::Storage);
+        for (r, n) in current.building_type.replace_cost(&new_building.building_type) {
+            match n > 0 {
+                true => resources.add(r, -n as i64),
+                false => resources.add_up_to(r, -n as i64, storage),
+            }
+        }
+
+        let footprint = Tile::footprint(i, &new_building.building_type);
+        for cell in &footprint {
+            self.add_count(self.land[cell.x as usize][cell.y as usize].building_type, -1);
+        }
+        self.add_count(new_building.building_type, 1);
+        for cell in footprint {
+            if cell != i {
+                self.parts.insert(cell, i);
             }
+            self.land[cell.x as usize][cell.y as usize] = new_building.clone();
         }
-        let adj = i.get_adjacent()[0..4]
+        let affected = self.affected(i, &new_building.building_type, current.building_type);
+        self.revalidate(affected, resources);
+        return true;
+    }
+
+    /// take a copy of the buildings and plans on the tile
+    pub fn snapshot(&self) -> TileState {
+        TileState {
+            land: self.land.each_ref().map(|x| x.each_ref().map(|b| b.building_type)),
+            planned: self.planned.clone(),
+            parts: self.parts.clone(),
+        }
+    }
+
+    /// put the tile back into the state of a snapshot
+    pub fn restore(&mut self, state: &TileState, resources: &mut ResourceBag) {
+        for x in 0..8 {
+            for y in 0..8 {
+                self.land[x][y] = Building::new(&state.land[x][y]);
+            }
+        }
+        self.planned = state.planned.clone();
+        self.parts = state.parts.clone();
+        self.update_count(resources);
+    }
+
+    /// check if a building is valid at a certain position
+    pub fn is_valid(&self, i: Pos, new_building: &Building) -> Result<(), Invalid> {
+        if new_building.building_type == BuildingType::Ground {
+            return Ok(());
+        }
+        // a cell covered by a bigger building is checked from the building's anchor
+        let i = match self.parts.get(&i) {
+            Some(a) if self.land[a.x as usize][a.y as usize].building_type == new_building.building_type => *a,
+            _ => i,
+        };
+        let cells = Tile::footprint(i, &new_building.building_type);
+        if !cells.iter().all(|x| in_bounds(*x)) {
+            return Err(Invalid::OutOfBounds);
+        }
+        let taken = cells
             .iter()
-            .filter(|x| x.x < 8 && x.x >= 0 && x.y < 8 && x.y >= 0)
-            .map(|x| self.land[x.x as usize][x.y as usize].building_type)
+            .filter(|x| *x != &i && self.anchor(**x) != i)
+            .map(|x| (*x, self.land[x.x as usize][x.y as usize].building_type))
+            .filter(|x| x.1 != BuildingType::Ground)
+            .collect::<Vec<(Pos, BuildingType)>>();
+        if !taken.is_empty() {
+            return Err(Invalid::Occupied(taken));
+        }
+        let missing = new_building
+            .tile_adj
+            .iter()
+            .filter(|x| !self.buildings.contains_key(x))
+            .copied()
             .collect::<Vec<BuildingType>>();
-
-        if !new_building.required_adj.iter().all(|x| adj.contains(x)) {
-            return false;
+        if !missing.is_empty() {
+            return Err(Invalid::MissingOnTile(missing));
         }
-        if !adj
+        let adj = self.neighbours(i, &new_building.building_type);
+
+        let missing = new_building
+            .required_adj
             .iter()
-            .all(|x| new_building.required_adj.contains(x) || new_building.optional_adj.contains(x))
-        {
-            return false;
+            .filter(|x| !adj.iter().any(|a| &a.1 == *x))
+            .copied()
+            .collect::<Vec<BuildingType>>();
+        if !missing.is_empty() {
+            return Err(Invalid::MissingNeighbour(missing));
         }
-        return true;
+        let forbidden = adj
+            .into_iter()
+            .filter(|x| !new_building.required_adj.contains(&x.1) && !new_building.optional_adj.contains(&x.1))
+            .collect::<Vec<(Pos, BuildingType)>>();
+        if !forbidden.is_empty() {
+            return Err(Invalid::ForbiddenNeighbour(forbidden));
+        }
+        return Ok(());
+    }
+
+    /// check that a building on a cell would be valid, and that it would not leave any other building on the tile invalid
+    pub fn is_valid_after(&self, i: Pos, new_building: &Building) -> Result<(), Invalid> {
+        let mut tile = self.clone();
+        tile.land[i.x as usize][i.y as usize] = new_building.clone();
+        tile.count_buildings();
+        tile.is_valid(i, new_building)?;
+        let mut broken = vec![];
+        for x in 0..8 {
+            for y in 0..8 {
+                let cell = Pos::new(x as i32, y as i32);
+                if cell != i
+                    && self.is_valid(cell, &self.land[x][y]).is_ok()
+                    && tile.is_valid(cell, &tile.land[x][y]).is_err()
+                {
+                    broken.push((cell, tile.land[x][y].building_type));
+                }
+            }
+        }
+        if !broken.is_empty() {
+            return Err(Invalid::BreaksOthers(broken));
+        }
+        Ok(())
+    }
+
+    /// check if a building could be put on a cell right now, including whether it can be paid for.
+    /// a building that replaces another one only costs the difference, and it must leave the rest of the tile valid
+    pub fn can_place(&self, i: Pos, new_building: &Building, resources: &ResourceBag) -> Result<(), Invalid> {
+        let current = self.land[i.x as usize][i.y as usize].building_type;
+        if current == new_building.building_type {
+            return Ok(());
+        }
+        // buildings bigger than one cell cannot be swapped in place
+        if current != BuildingType::Ground && (current.is_multi_cell() || new_building.building_type.is_multi_cell()) {
+            return Err(Invalid::Occupied(vec![(self.anchor(i), current)]));
+        }
+        match current {
+            BuildingType::Ground => self.is_valid(i, new_building)?,
+            _ => self.is_valid_after(i, new_building)?,
+        }
+        let short = resources.missing(&ResourceBag::of(&current.replace_cost(&new_building.building_type)));
+        if !short.is_empty() {
+            return Err(Invalid::Unaffordable(short));
+        }
+        Ok(())
     }
 
     /// return the tile as a string
@@ -158,121 +563,113 @@ impl Tile {
         return string.trim_end().to_owned();
     }
 
+    /// the buildings on the tile, most common first, for showing when the tile is zoomed out
+    pub fn summary_text(&self) -> String {
+        let mut buildings = self
+            .buildings
+            .iter()
+            .filter(|x| x.0 != &BuildingType::Ground && x.1 > &0)
+            .collect::<Vec<_>>();
+        buildings.sort_by_key(|x| (-x.1, x.0.name()));
+        format!(
+            "{}\n{}zoom in to build",
+            self.pos.to_string(),
+            buildings
+                .iter()
+                .map(|x| format!("{} {}: {}\n", x.0.symbol(), x.0.name(), x.1))
+                .collect::<String>()
+        )
+    }
+
+    /// draw the tile with macroquad as a block of colour for each building, instead of as egui widgets.
+    /// used when zoomed out, where there can be hundreds of tiles on the screen. `pixels_per_point`
+    /// turns egui points into screen pixels
+    pub fn draw_summary(&self, view: View, pixels_per_point: f32, palette: Palette) {
+        let color = to_macroquad;
+        let rect = view.tile_rect(self.pos);
+        let min = rect.min.to_vec2() * pixels_per_point;
+        let cell = view.cell_size() * pixels_per_point;
+        macroquad::shapes::draw_rectangle(
+            min.x,
+            min.y,
+            rect.width() * pixels_per_point,
+            rect.height() * pixels_per_point,
+            color(palette.land),
+        );
+        for (x, row) in self.land.iter().enumerate() {
+            for (y, b) in row.iter().enumerate() {
+                if b.building_type == BuildingType::Ground {
+                    continue;
+                }
+                macroquad::shapes::draw_rectangle(
+                    min.x + y as f32 * cell,
+                    min.y + x as f32 * cell,
+                    cell,
+                    cell,
+                    color(b.building_type.color()),
+                );
+            }
+        }
+    }
+
     pub fn render(
         &mut self,
         egui_ctx: &egui::Context,
         input_settings: &crate::InputSettings,
-        offset: (f32, f32),
-        resources: &mut HashMap<Resource, i32>,
+        view: View,
+        resources: &mut ResourceBag,
         enabled: bool,
-    ) -> bool {
+        history: &mut History,
+    ) -> TileResponse {
+        let mut tile_response = TileResponse::default();
         let mut changed = false;
         let mut set_buildings: Vec<(Pos, Building)> = vec![];
+        // the state of the tile before the player clicked on it, so that the click can be undone
+        let mut before: Option<(TileState, ResourceBag)> = None;
 
-        let mut s = false;
-
-        
-
-        let window_rect = egui::Rect::from_min_size(
-                            egui::Pos2::new(
-                offset.0 + self.pos.x as f32 * 202.0,
-                offset.1 + self.pos.y as f32 * 202.0,
-            ),
-                            egui::Vec2::new(202.0, 202.0),
-        );
-
-
+        let window_rect = view.tile_rect(self.pos);
+        let cell_size = view.cell_size();
+        let palette = Palette::of(egui_ctx.style().visuals.dark_mode);
 
         egui::Area::new(Id::new(self.pos.to_string()))
-            .fixed_pos(egui::Pos2::new(
-                offset.0 + self.pos.x as f32 * 202.0,
-                offset.1 + self.pos.y as f32 * 202.0,
-            ))
-            
+            .fixed_pos(window_rect.min)
+
             .order(egui::Order::Background)
             .show(egui_ctx, |ui| {
                 egui::Frame::none()
-    .fill(egui::Color32::from_rgb(215, 235, 210))
+    .fill(palette.land)
     .show(ui, |ui| {
                 let hover = window_rect.contains(egui_ctx.pointer_hover_pos().unwrap_or_default());
                 ui.style_mut().spacing.item_spacing = egui::Vec2::new(0.0, 0.0);
-                let mut c = false;
-                ui.input_mut(|i| {
-                    
-                        c = i.key_down(egui::Key::C);
-                        s = i.key_down(egui::Key::S);
-                    
-                    });
-                    
-                    
-                    
-                if false && (self.summary || s) {
-                //     ui.checkbox(&mut self.summary, "Better Performance");
-                
-                //         egui::Grid::new("grid")
-                //         .max_col_width(202.0)
-                //         .min_col_width(202.0)
-                        
-                //         .show(ui, |ui| {
-                    
-                //     for i in self.buildings.iter() {
-                //         if i.0 == &BuildingType::Ground {
-                //             continue;
-                //         }
-                //         ui.label(format!("{}: {}", i.0.symbol(), i.1));
-                //         ui.end_row();
-                    
-                // }});
-                
-                }{
-
-                if false && c {
-                    if ui.put(
-                        egui::Rect::from_min_size(
-                            egui::Pos2::new(0.0, 0.0),
-                            egui::Vec2::new(202.0, 202.0),
-                        )
-                        , egui::Button::new(format!("{}{} paste", egui_phosphor::CLIPBOARD, egui_phosphor::ARROW_FAT_LEFT)),
-                        ).clicked() {
-                            ui.output_mut(|o| {
-                                o.copied_text = self.land.concat().iter().map(|x|x.symbol.clone()).collect::<String>()
-                                ;
-                            });
-                        }
-
-                    if ui.put(
-                        egui::Rect::from_min_size(
-                            egui::Pos2::new(0.0, 0.0),
-                            egui::Vec2::new(202.0, 202.0),
-                        )
-                        , egui::Button::new(format!("{}{} paste", egui_phosphor::CLIPBOARD, egui_phosphor::ARROW_FAT_RIGHT)),
-                        ).clicked() {
-                            let mut potential_text = "".to_owned();
-                            ui.output_mut(|o| {
-                                // potential_text = o.copied_text;
-                            });
-
-                            if potential_text.len() == 64 {
-                                // let mut new_land = [[Building::new(BuildingType::Ground); 8]; 8];
-                                for (i, c) in potential_text.chars().enumerate() {
-                                    // new_land[i / 8][i % 8] = Building::new(BuildingType::from_symbol(c));
-                                }
-                                // self.land = new_land;
-                            }
-                    }
+                // the symbols grow and shrink with the zoom
+                for font in ui.style_mut().text_styles.values_mut() {
+                    font.size *= view.scale;
                 }
+                let c = input_settings.copy_tile;
 
-
+                // the screen rect of each cell, and why the tool cannot be used on the hovered cell
+                let mut rects: HashMap<Pos, egui::Rect> = HashMap::new();
+                let mut hover_reason: Option<Invalid> = None;
                 for i in 0..8 {
                     ui.horizontal(|ui| {
                         for j in 0..8 {
 
-                            let rich_text = egui::RichText::new(&self.land[i][j].symbol).to_owned();
-                            
-
+                            // buildings bigger than one cell have their symbol drawn once across all of their cells below
+                            let rich_text = match self.land[i][j].building_type.is_multi_cell() {
+                                true => egui::RichText::new(""),
+                                false => egui::RichText::new(&self.land[i][j].symbol).to_owned(),
+                            };
+                            let world_cell = Pos::world_cell(self.pos, Pos::new(i as i32, j as i32));
 
                             if !hover {
-                                let response = ui.add_sized([25.0,25.0], egui::Label::new(rich_text));
+                                let response = ui.add_sized([cell_size, cell_size], egui::Label::new(rich_text));
+                                rects.insert(Pos::new(i as i32, j as i32), response.rect);
+                                if let Some(b) = input_settings.preview.get(&world_cell) {
+                                    ui.put(
+                                        response.rect,
+                                        egui::Label::new(egui::RichText::new(b.symbol()).italics().weak()),
+                                    );
+                                }
                                 
                                 if let Some(b) = self.planned.get(&Pos {
                                     x: i as i32,
@@ -284,15 +681,9 @@ impl Tile {
                                             egui::RichText::new(&b.symbol()).weak().to_owned(),
                                         ),
                                     );
-
-    
-                                    set_buildings.push((
-                                        Pos {
-                                            x: i as i32,
-                                            y: j as i32,
-                                        },
-                                        Building::new(&b),
-                                    ));
+                                }
+                                if input_settings.inspected == Some(world_cell) {
+                                    ui.painter().rect_stroke(response.rect.shrink(1.0), 2.0, INSPECTED_STROKE);
                                 }
                                 continue;
                             }
@@ -300,36 +691,19 @@ impl Tile {
 
                             let square = egui::Button::new(rich_text)
                             
-                                .stroke(egui::Stroke::new(
-                                    1.0,
-                                    egui::Color32::from_rgb(200, 235, 200),
-                                ))
+                                .stroke(egui::Stroke::new(1.0, palette.cell_stroke))
                                 .fill(
                                     
-                                    match self.is_valid(Pos::new(i as i32, j as i32), &self.land[i][j]) {
-                                        true => egui::Color32::from_rgb(215, 235, 210),
-                                        false => egui::Color32::from_rgb(235, 215, 210),
+                                    match self.is_valid(Pos::new(i as i32, j as i32), &self.land[i][j]).is_ok() {
+                                        true => palette.land,
+                                        false => palette.invalid,
                                     })
-                                    
-                                    // egui::Color32::from_rgb(215, 235, 210))
                                 .small()
-                                .min_size(egui::Vec2::new(25.0, 25.0))
+                                .min_size(egui::Vec2::splat(cell_size))
                                 .sense(egui::Sense::click_and_drag());
                             let response = ui.add_enabled(
                                 !(enabled
-                                    && ui.rect_contains_pointer(
-                                        egui::Rect::from_two_pos(
-                                            egui::Pos2::new(
-                                                offset.0 + self.pos.x as f32 * 202.0,
-                                                offset.1 + self.pos.y as f32 * 202.0,
-                                            ),
-                                            egui::Pos2::new(
-                                                offset.0 + (self.pos.x + 1) as f32 * 202.0,
-                                                offset.1 + (self.pos.y + 1) as f32 * 202.0,
-                                            ),
-                                        )
-                                        .intersect(ui.cursor()),
-                                    )),
+                                    && ui.rect_contains_pointer(window_rect.intersect(ui.cursor()))),
                                 square,
                             );
                             let text = match &input_settings.edit_tool {
@@ -340,7 +714,7 @@ impl Tile {
                                             y: j as i32,
                                         },
                                         b,
-                                    ) && self.land[i][j].building_type == BuildingType::Ground) || input_settings.select_tool == SelectTool::Plan
+                                    ).is_ok() && self.land[i][j].building_type == BuildingType::Ground) || input_settings.select_tool == SelectTool::Plan
                                     {
                                         egui::RichText::new(&b.symbol).to_owned()
                                     } else {
@@ -354,8 +728,60 @@ impl Tile {
                                         .color(egui::Color32::from_rgb(255, 0, 0))
                                         .to_owned(),
                                 },
+                                EditTool::Copy => egui::RichText::new(egui_phosphor::SELECTION).weak(),
+                                // the blueprint is drawn from the preview instead
+                                EditTool::Blueprint(_) => egui::RichText::new(""),
                             };
-                            if response.hovered() || ui.input(|r| r.key_down(egui::Key::Q)) {
+                            rects.insert(Pos::new(i as i32, j as i32), response.rect);
+                            let mut response = response;
+                            if response.hovered() {
+                                tile_response.hovered = Some(world_cell);
+                                let cell = Pos::new(i as i32, j as i32);
+                                let current = self.land[i][j].building_type;
+                                hover_reason = match &input_settings.edit_tool {
+                                    _ if input_settings.select_tool == SelectTool::Inspect => {
+                                        self.is_valid(cell, &self.land[i][j]).err()
+                                    }
+                                    // plans are paid for when they are built
+                                    EditTool::Build(b) if input_settings.select_tool == SelectTool::Plan => {
+                                        self.is_valid(cell, b).err()
+                                    }
+                                    EditTool::Build(b) if b.building_type != current => {
+                                        self.can_place(cell, b, resources).err()
+                                    }
+                                    _ => self.is_valid(cell, &self.land[i][j]).err(),
+                                };
+                                if let Some(reason) = &hover_reason {
+                                    response = response.on_hover_text(reason.describe());
+                                }
+                            }
+                            if let Some(b) = input_settings.preview.get(&world_cell) {
+                                ui.put(
+                                    response.rect,
+                                    egui::Label::new(match b {
+                                        BuildingType::Ground => egui::RichText::new(egui_phosphor::X)
+                                            .color(egui::Color32::from_rgb(255, 0, 0)),
+                                        b => egui::RichText::new(b.symbol()).italics().weak(),
+                                    }),
+                                );
+                            }
+                            if let Some((a, b)) = input_settings.selection {
+                                if (a.x.min(b.x)..=a.x.max(b.x)).contains(&world_cell.x)
+                                    && (a.y.min(b.y)..=a.y.max(b.y)).contains(&world_cell.y)
+                                {
+                                    ui.painter().rect_stroke(
+                                        response.rect.shrink(1.0),
+                                        2.0,
+                                        egui::Stroke::new(1.0, egui::Color32::from_rgb(80, 120, 220)),
+                                    );
+                                }
+                            }
+                            if input_settings.inspected == Some(world_cell) {
+                                ui.painter().rect_stroke(response.rect.shrink(1.0), 2.0, INSPECTED_STROKE);
+                            }
+                            if (response.hovered() || input_settings.show_symbols)
+                                && input_settings.select_tool != SelectTool::Inspect
+                            {
                                 ui.put(response.rect, egui::Label::new(text));
                             }
 
@@ -369,18 +795,18 @@ impl Tile {
                                             egui::RichText::new(&b.symbol()).weak().to_owned(),
                                         ),
                                     );
-    
-                                    set_buildings.push((
-                                        Pos {
-                                            x: i as i32,
-                                            y: j as i32,
-                                        },
-                                        Building::new(&b),
-                                    ));
                                 }
-                            
 
-                            if response.clicked() {
+
+                            if response.clicked()
+                                && (matches!(input_settings.edit_tool, EditTool::Copy | EditTool::Blueprint(_))
+                                    || input_settings.select_tool == SelectTool::Inspect)
+                            {
+                                tile_response.clicked = Some(world_cell);
+                            } else if response.clicked() {
+                                if before.is_none() {
+                                    before = Some((self.snapshot(), *resources));
+                                }
                                 if input_settings.select_tool == SelectTool::Plan {
                                     self.planned.insert(
                                         Pos {
@@ -389,9 +815,12 @@ impl Tile {
                                         },
                                         match &input_settings.edit_tool {
                                             crate::EditTool::Build(b) => b.building_type.clone(),
-                                            crate::EditTool::Remove => BuildingType::Ground,
+                                            _ => BuildingType::Ground,
                                         },
                                     );
+                                    // plans are built by the build queue when the economy ticks
+                                    changed = true;
+                                    continue;
                                 }
                                 set_buildings.push((
                                     Pos {
@@ -400,7 +829,7 @@ impl Tile {
                                     },
                                     match &input_settings.edit_tool {
                                         crate::EditTool::Build(b) => b.clone(),
-                                        crate::EditTool::Remove => {
+                                        _ => {
                                             Building::new(&BuildingType::Ground)
                                         }
                                     },
@@ -409,68 +838,103 @@ impl Tile {
                     }
                     });
                 };
-            }
-                
-                });
-            });
-
-        for (i, new_building) in set_buildings {
-            let mut break_building = false;
-
-            let ground = Building::new(&BuildingType::Ground);
-
-            let current = &self.land[i.x as usize][i.y as usize];
-            if current.building_type == new_building.building_type {
-                self.planned.remove(&i);
-                continue;
-            }
-
-            if new_building.building_type == BuildingType::Ground {
-                for i in Building::new(&current.building_type).cost {
-                    let storage = resources.get(&Resource::Storage).unwrap_or(&100);
-                    resources.insert(i.0, (resources.get(&i.0).unwrap_or(&0) + i.1).min(*storage));
+                for (x, row) in self.land.iter().enumerate() {
+                    for (y, b) in row.iter().enumerate() {
+                        let anchor = Pos::new(x as i32, y as i32);
+                        if !b.building_type.is_multi_cell() || self.parts.contains_key(&anchor) {
+                            continue;
+                        }
+                        let rect = Tile::footprint(anchor, &b.building_type)
+                            .iter()
+                            .filter_map(|x| rects.get(x))
+                            .fold(egui::Rect::NOTHING, |a, r| a.union(*r));
+                        ui.painter().rect_stroke(
+                            rect.shrink(2.0),
+                            3.0,
+                            egui::Stroke::new(1.0, ui.visuals().weak_text_color()),
+                        );
+                        ui.painter().text(
+                            rect.center(),
+                            egui::Align2::CENTER_CENTER,
+                            &b.symbol,
+                            egui::FontId::proportional(rect.width().min(rect.height()) * 0.6),
+                            ui.visuals().text_color(),
+                        );
+                    }
                 }
 
-                self.land[i.x as usize][i.y as usize] = ground;
-                self.update_count(resources);
-                changed = true;
-                continue;
-            }
-
-            if !self.is_valid(i, &new_building) {
-                continue;
-            }
-
-            for i in new_building.cost.iter() {
-                if let Some(x) = resources.get(&i.0) {
-                    if *x < i.1 {
-                        break_building = true;
+                // mark buildings that are cut off from the road network, and colour the roads when the overlay is on
+                for (anchor, b) in &self.disconnected {
+                    let rect = Tile::footprint(*anchor, b)
+                        .iter()
+                        .filter_map(|x| rects.get(x))
+                        .fold(egui::Rect::NOTHING, |a, r| a.union(*r));
+                    if rect.is_positive() {
+                        ui.painter().circle_filled(rect.right_top() + egui::vec2(-5.0, 5.0), 3.0, DISCONNECTED_COLOR);
+                    }
+                }
+                if input_settings.road_overlay {
+                    for (cell, connected) in &self.roads {
+                        if let Some(rect) = rects.get(cell) {
+                            ui.painter().rect_filled(
+                                *rect,
+                                0.0,
+                                match connected {
+                                    true => egui::Color32::from_rgba_unmultiplied(60, 180, 80, 110),
+                                    false => egui::Color32::from_rgba_unmultiplied(220, 60, 60, 110),
+                                },
+                            );
+                        }
                     }
-                } else {
-                    break_building = true;
                 }
-            }
-
-            if break_building {
-                continue;
-            }
-
 
-            self.planned.remove(&i);
+                // outline the cells that stop the hovered cell from being valid
+                if let Some(reason) = &hover_reason {
+                    for rect in reason.cells().iter().filter_map(|x| rects.get(x)) {
+                        ui.painter().rect_stroke(
+                            rect.shrink(1.0),
+                            2.0,
+                            egui::Stroke::new(2.0, egui::Color32::from_rgb(220, 60, 60)),
+                        );
+                    }
+                }
 
-            for i in new_building.cost.iter() {
-                if let Some(x) = resources.get_mut(&i.0) {
-                    *x -= i.1;
+                // holding the copy key (C unless it was changed) over a tile shows a button to copy the whole tile into a blueprint
+                if c && hover && ui
+                    .put(
+                        window_rect.shrink(40.0),
+                        egui::Button::new(format!("{} copy tile", egui_phosphor::COPY)),
+                    )
+                    .clicked()
+                {
+                    tile_response.copy_tile = true;
                 }
-            }
+                
+                });
+            });
 
-            self.land[i.x as usize][i.y as usize] = new_building;
-            changed = true;
+        for (i, new_building) in set_buildings {
+            if self.place(i, new_building.clone(), resources) {
+                changed = true;
+            } else if let Err(reason) = self.can_place(i, &new_building, resources) {
+                tile_response.failed = Some(reason);
+            }
         }
-        if changed {
-            self.update_count(resources);
+
+        if let Some((state, old_resources)) = before {
+            let after = self.snapshot();
+            let delta = *resources - old_resources;
+            if after != state || !delta.is_empty() {
+                history.push(Action::Edit {
+                    tile: self.pos,
+                    before: Box::new(state),
+                    after: Box::new(after),
+                    resources: delta,
+                });
+            }
         }
 
-        return changed;
+        tile_response.changed = changed;
+        return tile_response;
     }
 }
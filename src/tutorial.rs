@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+
+use egui::{Color32, Rect, Stroke};
+use strum::IntoEnumIterator;
+use strum_macros::EnumIter;
+
+use crate::{
+    building::{BuildingType, Resource},
+    tile::Pos,
+    Data, EditTool, TICK_SECS,
+};
+
+/// the colour that the part of the screen a step is about is outlined in
+const HIGHLIGHT_COLOR: Color32 = Color32::from_rgb(255, 127, 80);
+
+/// the amount of seed the last step asks for
+const SEED_GOAL: i64 = 50;
+
+/// a part of the screen that a step can point at. the widgets mark where they are each frame
+#[derive(PartialEq, Eq, Hash, Copy, Clone)]
+pub enum Target {
+    /// the button that opens the building menu
+    BuildMenu,
+    /// the button for a building in the building menu
+    Building(BuildingType),
+    /// a resource in the top left corner
+    Resource(Resource),
+    /// the first piece of land
+    Land,
+}
+
+/// one thing the tutorial asks the player to do
+#[derive(PartialEq, Eq, Copy, Clone, EnumIter)]
+enum Step {
+    OpenBuildMenu,
+    PlaceHouse,
+    PlaceGrain,
+    ReachSeed,
+}
+
+impl Step {
+    fn title(&self) -> &'static str {
+        match self {
+            Step::OpenBuildMenu => "Open the building menu",
+            Step::PlaceHouse => "Build a House",
+            Step::PlaceGrain => "Plant some Grain",
+            Step::ReachSeed => "Save up some seed",
+        }
+    }
+
+    fn text(&self) -> String {
+        match self {
+            Step::OpenBuildMenu => "the green square is your land, and the numbers in the top left are your resources. \
+                everything you can build is in the building menu, which opens from the bottom left of the screen"
+                .to_owned(),
+            Step::PlaceHouse => "pick the House in the building menu and click a cell on your land to build it. \
+                hover over a building in the menu to see what it costs and where it can go"
+                .to_owned(),
+            Step::PlaceGrain => "Grain makes food and seed. it needs a House on the same land or the land next to it, \
+                but it cannot touch one. pick Grain and plant it near your House, leaving a gap between them"
+                .to_owned(),
+            Step::ReachSeed => format!(
+                "buildings make resources every tick, which is every {} seconds. \
+                wait for your seed to reach {}, or plant more Grain to get there sooner",
+                TICK_SECS, SEED_GOAL
+            ),
+        }
+    }
+
+    /// check if the player has done what the step asks
+    fn done(&self, data: &Data) -> bool {
+        let has = |b: BuildingType| data.tiles.values().any(|t| t.buildings.get(&b).unwrap_or(&0) > &0);
+        match self {
+            Step::OpenBuildMenu => data.popup,
+            Step::PlaceHouse => has(BuildingType::House),
+            // grain can only be placed with a house on its land or the land next to it, so any grain will do
+            Step::PlaceGrain => has(BuildingType::Grain),
+            Step::ReachSeed => data.totals().get(Resource::Seed) >= SEED_GOAL,
+        }
+    }
+
+    /// the parts of the screen the step is about, best first. the first one that is on the screen is outlined
+    fn targets(&self, data: &Data) -> Vec<Target> {
+        let placing = |b: BuildingType| matches!(&data.input_settings.edit_tool, EditTool::Build(x) if x.building_type == b);
+        match self {
+            Step::OpenBuildMenu => vec![Target::BuildMenu],
+            Step::PlaceHouse | Step::PlaceGrain => {
+                let b = match self {
+                    Step::PlaceHouse => BuildingType::House,
+                    _ => BuildingType::Grain,
+                };
+                match placing(b) {
+                    true => vec![Target::Land],
+                    false => vec![Target::Building(b), Target::BuildMenu],
+                }
+            }
+            Step::ReachSeed => vec![Target::Resource(Resource::Seed)],
+        }
+    }
+}
+
+/// how far the player is through the tutorial. saved with the city
+#[derive(Savefile, Default)]
+pub struct Tutorial {
+    /// the step the player is on. once it is past the last step the tutorial is finished
+    step: usize,
+    /// the player closed the tutorial. it can be opened again with the Tutorial button
+    pub hidden: bool,
+    /// where the widgets that steps can point at were drawn this frame
+    #[savefile_introspect_ignore]
+    #[savefile_ignore]
+    targets: HashMap<Target, Rect>,
+}
+
+impl Tutorial {
+    /// note where a widget that a step can point at was drawn
+    pub fn mark(&mut self, target: Target, rect: Rect) {
+        self.targets.insert(target, rect);
+    }
+
+    /// a tutorial that is already finished and closed, for cities from before it was added
+    pub fn finished() -> Tutorial {
+        Tutorial {
+            step: Step::iter().count(),
+            hidden: true,
+            targets: HashMap::new(),
+        }
+    }
+
+    fn current(&self) -> Option<Step> {
+        Step::iter().nth(self.step)
+    }
+}
+
+impl Data {
+    /// move past the steps that are done, then render the current step and outline what it is about
+    pub fn render_tutorial(&mut self, egui_ctx: &egui::Context) {
+        while let Some(step) = self.tutorial.current() {
+            if !step.done(self) {
+                break;
+            }
+            self.tutorial.step += 1;
+            if !self.tutorial.hidden {
+                self.info(format!("{} done", step.title()));
+            }
+        }
+        if let Some(tile) = self.tiles.get(&Pos::new(0, 0)) {
+            let rect = self.view().tile_rect(tile.pos);
+            self.tutorial.mark(Target::Land, rect);
+        }
+        let targets = std::mem::take(&mut self.tutorial.targets);
+        if self.tutorial.hidden {
+            return;
+        }
+
+        let step = self.tutorial.current();
+        let mut open = true;
+        egui::Window::new(format!("{} Tutorial", egui_phosphor::GRADUATION_CAP))
+            .open(&mut open)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+            .show(egui_ctx, |ui| {
+                ui.set_max_width(260.0);
+                match step {
+                    Some(step) => {
+                        ui.small(format!("step {} of {}", self.tutorial.step + 1, Step::iter().count()));
+                        ui.heading(step.title());
+                        ui.label(step.text());
+                        if step == Step::ReachSeed {
+                            let seed = self.totals().get(Resource::Seed);
+                            ui.add(
+                                egui::ProgressBar::new(seed as f32 / SEED_GOAL as f32)
+                                    .text(format!("{} {} / {}", Resource::Seed.symbol(), seed, SEED_GOAL)),
+                            );
+                        }
+                        if ui.small_button("skip this step").clicked() {
+                            self.tutorial.step += 1;
+                        }
+                    }
+                    None => {
+                        ui.heading("Tutorial complete");
+                        ui.label("keep growing your city. new buildings unlock as you reach each stage, which the building menu shows");
+                    }
+                }
+            });
+        if !open {
+            self.tutorial.hidden = true;
+        }
+
+        if let Some(step) = step {
+            if let Some(rect) = step.targets(self).iter().find_map(|t| targets.get(t)) {
+                egui_ctx
+                    .layer_painter(egui::LayerId::new(egui::Order::Tooltip, egui::Id::new("tutorial highlight")))
+                    .rect_stroke(rect.expand(3.0), 4.0, Stroke::new(2.0, HIGHLIGHT_COLOR));
+            }
+        }
+    }
+}